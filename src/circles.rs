@@ -10,9 +10,17 @@ pub struct CircleConverter {
 impl CircleConverter {
     pub fn new(n_points: usize, axis_shift: f64) -> CircleConverter {
         let phi_step = TAU / { n_points as f64 };
+        CircleConverter::with_angles(n_points, axis_shift, |n| n as f64 * phi_step)
+    }
+
+    pub fn with_angles(
+        n_points: usize,
+        axis_shift: f64,
+        angle: impl Fn(usize) -> f64,
+    ) -> CircleConverter {
         let sin_cos_table = (0..n_points)
             .chain(Some(0))
-            .map(|n| (n as f64 * phi_step).sin_cos())
+            .map(|n| angle(n).sin_cos())
             .collect::<Vec<_>>();
         CircleConverter {
             sin_cos_table: sin_cos_table,
@@ -28,6 +36,14 @@ impl CircleConverter {
         (x, y)
     }
 
+    // Point shifted from circle point by given distance along tangent (counterclockwise)
+    pub fn get_xy_local(&self, n: usize, radial: f64, tangential: f64) -> (f64, f64) {
+        let (sin_phi, cos_phi) = self.sin_cos_table[n];
+        let x: f64 = radial * cos_phi - tangential * sin_phi + self.axis_shift;
+        let y: f64 = radial * sin_phi + tangential * cos_phi + self.axis_shift;
+        (x, y)
+    }
+
     pub fn get_vector3(&self, n: usize, rho: f64, z: f64) -> Vector3 {
         let (x, y) = self.get_xy(n, rho);
         Vector3::new(x, y, z)
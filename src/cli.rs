@@ -1,5 +1,12 @@
+use crate::filters::Filter;
+use crate::image::{HeightSource, TransparentLevel};
+use crate::layers::{BlendOperator, Layer, LayerSource};
+use crate::pattern::Pattern;
+use crate::threshold::Threshold;
+use crate::tone::check_curve_points;
+use crate::transform::{Crop, Padding};
 use clap::builder::NonEmptyStringValueParser;
-use clap::ArgAction::SetTrue;
+use clap::ArgAction::{Append, SetTrue};
 use clap::{value_parser, Arg, ArgGroup, Command};
 
 pub fn cli_command() -> Command<'static> {
@@ -15,8 +22,8 @@ pub fn cli_command() -> Command<'static> {
         ))
         .arg(
             Arg::new("filename")
-                .help("Filename of input image to be used as pattern")
-                .required(true)
+                .help("Filename of input image to be used as pattern (raster, SVG or raw .r16/.r32)")
+                .required_unless_present_any(["text", "pattern", "band", "segment"])
                 .value_name("IMGFILE")
                 .value_parser(NonEmptyStringValueParser::new())
                 .index(1),
@@ -45,10 +52,13 @@ pub fn cli_command() -> Command<'static> {
             Arg::new("grid_step")
                 .long("grid-step")
                 .short('g')
-                .value_name("STEP")
-                .help("Distance between vertices on roller surface (input image is resized accordingly)")
+                .value_name("STEP[,AXIAL]")
+                .help(concat!(
+                    "Distance between vertices on roller surface (input image is resized accordingly), ",
+                    "optionally separate for circumferential and axial directions"
+                ))
                 .takes_value(true)
-                .value_parser(value_parser!(f64))
+                .value_parser(parse_grid_step)
                 .display_order(3),
         )
         .arg(
@@ -61,6 +71,15 @@ pub fn cli_command() -> Command<'static> {
                 .value_parser(value_parser!(f64))
                 .display_order(4),
         )
+        .arg(
+            Arg::new("relief_direction")
+                .long("relief-direction")
+                .value_name("DIRECTION")
+                .help("Relief is cut inward from diameter (default), raised outward from it or centered on it")
+                .takes_value(true)
+                .value_parser(["inward", "outward", "centered"])
+                .display_order(5),
+        )
         .arg(
             Arg::new("pin_diameter")
                 .long("pin-diameter")
@@ -102,6 +121,14 @@ pub fn cli_command() -> Command<'static> {
                 .value_parser(NonEmptyStringValueParser::new())
                 .display_order(41),
         )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .action(SetTrue)
+                .help("Check that resulting mesh is watertight, manifold, consistently oriented and free of degenerate triangles and self-intersections")
+                .takes_value(false)
+                .display_order(42),
+        )
         .arg(
             Arg::new("stack_vertical")
                 .long("stack-vertical")
@@ -122,6 +149,213 @@ pub fn cli_command() -> Command<'static> {
                 .value_parser(value_parser!(u32).range(1..=1000))
                 .display_order(52),
         )
+        .arg(
+            Arg::new("stack_vertical_rule")
+                .long("stack-vertical-rule")
+                .visible_alias("svr")
+                .value_name("RULE")
+                .help("Transform of every second vertical copy: repeat, mirror or rotate (by 180°)")
+                .takes_value(true)
+                .value_parser(["repeat", "mirror", "rotate"])
+                .requires("stack_vertical")
+                .display_order(53),
+        )
+        .arg(
+            Arg::new("stack_horizontal_rule")
+                .long("stack-horizontal-rule")
+                .visible_alias("shr")
+                .value_name("RULE")
+                .help("Transform of every second horizontal copy: repeat, mirror or rotate (by 180°)")
+                .takes_value(true)
+                .value_parser(["repeat", "mirror", "rotate"])
+                .requires("stack_horizontal")
+                .display_order(54),
+        )
+        .arg(
+            Arg::new("seam_threshold")
+                .long("seam-threshold")
+                .value_name("FRACTION")
+                .help("Warn if image edges meeting at seams differ by more than this fraction of relief depth, beyond differences of neighbouring pixels inside image (default 0.1)")
+                .takes_value(true)
+                .value_parser(parse_seam_threshold)
+                .display_order(61),
+        )
+        .arg(
+            Arg::new("seam_blend")
+                .long("seam-blend")
+                .value_name("PIXELS")
+                .help("Width of band along image edges blended to hide seams between copies")
+                .takes_value(true)
+                .value_parser(value_parser!(u32).range(1..))
+                .display_order(62),
+        )
+        .arg(
+            Arg::new("seam_blend_mode")
+                .long("seam-blend-mode")
+                .value_name("MODE")
+                .help("Seam blending mode: crossfade (shift edges to common level) or feather (mix with mirrored edge)")
+                .takes_value(true)
+                .value_parser(["crossfade", "feather"])
+                .requires("seam_blend")
+                .display_order(63),
+        )
+        .arg(
+            Arg::new("text")
+                .long("text")
+                .value_name("TEXT")
+                .help("Render single line of text as pattern instead of input image (repeat it with horizontal stacking)")
+                .takes_value(true)
+                .value_parser(NonEmptyStringValueParser::new())
+                .conflicts_with("filename")
+                .requires("font")
+                .display_order(81),
+        )
+        .arg(
+            Arg::new("font")
+                .long("font")
+                .value_name("FONTFILE")
+                .help("TrueType or OpenType font file used to render text")
+                .takes_value(true)
+                .value_parser(NonEmptyStringValueParser::new())
+                .display_order(82),
+        )
+        .arg(
+            Arg::new("pattern")
+                .long("pattern")
+                .value_name("PATTERN")
+                .help(concat!(
+                    "Generate seamless texture instead of input image (needs both diameter and length): ",
+                    "ribs:PITCH[:ANGLE], knurl:PITCH[:ANGLE], dots:PITCH[:DIAMETER], noise:SCALE ",
+                    "or voronoi:CELL (sizes in mm, angles in degrees from roller axis)"
+                ))
+                .takes_value(true)
+                .value_parser(parse_pattern)
+                .conflicts_with_all(&["filename", "text"])
+                .display_order(83),
+        )
+        .arg(
+            Arg::new("pattern_profile")
+                .long("pattern-profile")
+                .value_name("PROFILE")
+                .help("Cross-section of pattern ribs and dots: sine (default), triangle or square")
+                .takes_value(true)
+                .value_parser(["sine", "triangle", "square"])
+                .display_order(84),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seed of random pattern (noise and voronoi)")
+                .takes_value(true)
+                .value_parser(value_parser!(u64))
+                .display_order(85),
+        )
+        .arg(
+            Arg::new("band")
+                .long("band")
+                .value_name("IMGFILE[:COPIES]")
+                .help(concat!(
+                    "Compose roller from bands along its length (from top) instead of input image, ",
+                    "can be repeated, copies of band image are placed around circumference"
+                ))
+                .takes_value(true)
+                .action(Append)
+                .value_parser(parse_collage_part)
+                .conflicts_with_all(&["filename", "text", "pattern", "segment"])
+                .display_order(86),
+        )
+        .arg(
+            Arg::new("segment")
+                .long("segment")
+                .value_name("IMGFILE[:COPIES]")
+                .help(concat!(
+                    "Compose roller from segments around its circumference instead of input image, ",
+                    "can be repeated, copies of segment image are placed along length"
+                ))
+                .takes_value(true)
+                .action(Append)
+                .value_parser(parse_collage_part)
+                .conflicts_with_all(&["filename", "text", "pattern"])
+                .display_order(87),
+        )
+        .arg(
+            Arg::new("layer")
+                .long("layer")
+                .value_name("LAYER")
+                .help(concat!(
+                    "Blend layer over pattern, can be repeated: OPERATOR[:SCALE]:SOURCE, ",
+                    "operators are max, min, add, multiply and mask, layer heights are multiplied by SCALE, ",
+                    "sources are image:IMGFILE, pattern:PATTERN and text:TEXT (fitted into image copy)"
+                ))
+                .takes_value(true)
+                .action(Append)
+                .value_parser(parse_layer)
+                .display_order(88),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .short('f')
+                .value_name("FILTER")
+                .help(concat!(
+                    "Preprocess image before resize, can be repeated to make chain of filters: ",
+                    "blur:SIGMA, sharpen:SIGMA[:AMOUNT], median:RADIUS, sobel, canny[:LOW:HIGH] ",
+                    "(sizes in image pixels, canny thresholds are fractions of strongest edge)"
+                ))
+                .takes_value(true)
+                .action(Append)
+                .value_parser(parse_filter)
+                .display_order(90),
+        )
+        .arg(
+            Arg::new("raw_size")
+                .long("raw-size")
+                .value_name("WIDTHxHEIGHT")
+                .help("Size in pixels of headerless raw height maps (.r16 is 16-bit, .r32 is 32-bit float, little-endian)")
+                .takes_value(true)
+                .value_parser(parse_raw_size)
+                .display_order(91),
+        )
+        .arg(
+            Arg::new("crop")
+                .long("crop")
+                .value_name("X,Y,WIDTH,HEIGHT")
+                .help("Crop rectangle of raster image in pixels (before rotation)")
+                .takes_value(true)
+                .value_parser(parse_crop)
+                .display_order(92),
+        )
+        .arg(
+            Arg::new("rotate")
+                .long("rotate")
+                .value_name("DEGREES")
+                .help("Rotate raster image clockwise, corners are transparent unless angle is multiple of 90")
+                .takes_value(true)
+                .value_parser(parse_angle)
+                .display_order(93),
+        )
+        .arg(
+            Arg::new("flip")
+                .long("flip")
+                .value_name("FLIP")
+                .help("Flip raster image (after rotation): horizontal, vertical or both")
+                .takes_value(true)
+                .value_parser(["horizontal", "vertical", "both"])
+                .display_order(94),
+        )
+        .arg(
+            Arg::new("pad")
+                .long("pad")
+                .value_name("MM")
+                .help(concat!(
+                    "Transparent margins around image copy in mm: ALL, VERTICAL,HORIZONTAL or TOP,RIGHT,BOTTOM,LEFT ",
+                    "(included in given roller dimension), transparent areas are at surface by default"
+                ))
+                .takes_value(true)
+                .value_parser(parse_padding)
+                .display_order(95),
+        )
         .arg(
             Arg::new("pixelated")
                 .long("pixelated")
@@ -141,11 +375,238 @@ pub fn cli_command() -> Command<'static> {
                 .takes_value(false)
                 .display_order(101),
         )
+        .arg(
+            Arg::new("absolute_levels")
+                .long("absolute-levels")
+                .short('a')
+                .action(SetTrue)
+                .help("Map black to full relief depth and white to surface regardless of image content")
+                .takes_value(false)
+                .conflicts_with("input_levels")
+                .display_order(105),
+        )
+        .arg(
+            Arg::new("heights_in_mm")
+                .long("heights-in-mm")
+                .action(SetTrue)
+                .help(concat!(
+                    "Use float image values as relief heights in mm above its bottom (depths below surface if inverted), ",
+                    "relief depth defaults to highest value"
+                ))
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "absolute_levels",
+                    "input_levels",
+                    "output_levels",
+                    "gamma",
+                    "contrast",
+                    "tone_curve",
+                    "tone_lut",
+                ])
+                .display_order(104),
+        )
+        .arg(
+            Arg::new("input_levels")
+                .long("input-levels")
+                .value_name("BLACK,WHITE")
+                .help(concat!(
+                    "Input levels (from 0 to 1, raw values for float images) mapped to full relief depth and surface, ",
+                    "instead of image min/max"
+                ))
+                .takes_value(true)
+                .value_parser(parse_input_levels)
+                .display_order(106),
+        )
+        .arg(
+            Arg::new("output_levels")
+                .long("output-levels")
+                .value_name("LOW,HIGH")
+                .help("Output levels (from 0 to 1) as fractions of relief depth measured from its bottom")
+                .takes_value(true)
+                .value_parser(parse_output_levels)
+                .display_order(107),
+        )
+        .arg(
+            Arg::new("gamma")
+                .long("gamma")
+                .value_name("GAMMA")
+                .help("Gamma correction of relief heights (values above 1 raise mid-tones)")
+                .takes_value(true)
+                .value_parser(parse_gamma)
+                .display_order(108),
+        )
+        .arg(
+            Arg::new("contrast")
+                .long("contrast")
+                .value_name("FACTOR")
+                .help("Contrast of relief heights around mid-level (values above 1 increase contrast)")
+                .takes_value(true)
+                .value_parser(parse_contrast)
+                .display_order(109),
+        )
+        .arg(
+            Arg::new("tone_curve")
+                .long("tone-curve")
+                .value_name("X:Y,...")
+                .help("Tone curve of relief heights as control points (from 0 to 1) with linear interpolation")
+                .takes_value(true)
+                .value_parser(parse_tone_curve)
+                .conflicts_with("tone_lut")
+                .display_order(110),
+        )
+        .arg(
+            Arg::new("tone_lut")
+                .long("tone-lut")
+                .value_name("LUTFILE")
+                .help("Tone curve of relief heights as text file with evenly spaced output values (from 0 to 1)")
+                .takes_value(true)
+                .value_parser(NonEmptyStringValueParser::new())
+                .display_order(111),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("LEVEL")
+                .help(concat!(
+                    "Quantize relief to surface and full depth only, using fixed level (from 0 to 1), ",
+                    "otsu (automatic level) or adaptive:RADIUS (local mean level); implies crisp walls"
+                ))
+                .takes_value(true)
+                .value_parser(parse_threshold)
+                .display_order(112),
+        )
+        .arg(
+            Arg::new("crisp_walls")
+                .long("crisp-walls")
+                .action(SetTrue)
+                .help("Make almost vertical walls between pixels instead of slopes (4 times more faces)")
+                .takes_value(false)
+                .display_order(113),
+        )
+        .arg(
+            Arg::new("terraces")
+                .long("terraces")
+                .value_name("LEVELS")
+                .help("Quantize relief into given number of levels (including surface)")
+                .takes_value(true)
+                .value_parser(value_parser!(u32).range(2..))
+                .conflicts_with("layer_height")
+                .display_order(114),
+        )
+        .arg(
+            Arg::new("layer_height")
+                .long("layer-height")
+                .value_name("HEIGHT")
+                .help("Quantize relief into levels spaced by given height from surface")
+                .takes_value(true)
+                .value_parser(value_parser!(f64))
+                .display_order(115),
+        )
+        .arg(
+            Arg::new("min_feature")
+                .long("min-feature")
+                .value_name("SIZE")
+                .help("Detect relief ridges and grooves narrower than given size (using grid step)")
+                .takes_value(true)
+                .value_parser(value_parser!(f64))
+                .display_order(116),
+        )
+        .arg(
+            Arg::new("min_feature_action")
+                .long("min-feature-action")
+                .value_name("ACTION")
+                .help("Action for too narrow features: report (default, list their locations) or remove (morphological opening and closing)")
+                .takes_value(true)
+                .value_parser(["report", "remove"])
+                .requires("min_feature")
+                .display_order(117),
+        )
+        .arg(
+            Arg::new("relief_along_normals")
+                .long("relief-along-normals")
+                .action(SetTrue)
+                .help("Offset relief along smoothed surface normals instead of radially")
+                .takes_value(false)
+                .display_order(118),
+        )
+        .arg(
+            Arg::new("normal_smoothing")
+                .long("normal-smoothing")
+                .value_name("SIGMA")
+                .help("Gaussian smoothing radius in pixels for surface normals (default 1)")
+                .takes_value(true)
+                .value_parser(value_parser!(f64))
+                .requires("relief_along_normals")
+                .display_order(119),
+        )
+        .arg(
+            Arg::new("quad_split")
+                .long("quad-split")
+                .value_name("STRATEGY")
+                .help(concat!(
+                    "Choice of surface quad diagonal: lls (default, best linear fit of radii), shortest, ",
+                    "dihedral (flattest pair of triangles), alternating or delaunay (largest minimal angle)"
+                ))
+                .takes_value(true)
+                .value_parser(["lls", "shortest", "dihedral", "alternating", "delaunay"])
+                .display_order(120),
+        )
+        .arg(
+            Arg::new("subdivide")
+                .long("subdivide")
+                .value_name("FACTOR")
+                .help("Split each pixel step into given number of vertex steps with bicubic interpolation of radii")
+                .takes_value(true)
+                .value_parser(value_parser!(u32).range(1..))
+                .conflicts_with("crisp_walls")
+                .display_order(122),
+        )
+        .arg(
+            Arg::new("adaptive_mesh")
+                .long("adaptive-mesh")
+                .value_name("TOLERANCE")
+                .help("Merge flat and evenly sloped surface areas into larger triangles, deviating by up to given distance")
+                .takes_value(true)
+                .value_parser(value_parser!(f64))
+                .conflicts_with("relief_along_normals")
+                .display_order(121),
+        )
+        .arg(
+            Arg::new("height_source")
+                .long("height-source")
+                .value_name("SOURCE")
+                .help(concat!(
+                    "Image property used as height: luma (default, alpha for SVG and text), red, green, blue, alpha, ",
+                    "value, saturation or weighted mix of channels (mix:R,G,B)"
+                ))
+                .takes_value(true)
+                .value_parser(parse_height_source)
+                .display_order(102),
+        )
+        .arg(
+            Arg::new("transparent_level")
+                .long("transparent-level")
+                .short('t')
+                .value_name("LEVEL")
+                .help("Level of transparent image areas: surface, bottom or depth value (alpha channel is ignored if not set)")
+                .takes_value(true)
+                .value_parser(parse_transparent_level)
+                .display_order(103),
+        )
+        .arg(
+            Arg::new("mirror_for_imprint")
+                .long("mirror-for-imprint")
+                .short('m')
+                .action(SetTrue)
+                .help("Mirror image along circumference so that imprint made by roller reads correctly")
+                .takes_value(false)
+                .display_order(96),
+        )
         .group(
             ArgGroup::new("roller_dimensions")
                 .args(&["roller_diameter", "roller_length"])
                 .required(true)
-                .multiple(false),
+                .multiple(true),
         )
         .group(
             ArgGroup::new("pin_dimensions")
@@ -155,4 +616,321 @@ pub fn cli_command() -> Command<'static> {
                 .multiple(true)
                 .conflicts_with("channel_diameter"),
         )
+        .subcommand(
+            Command::new("validate-stl")
+                .about("Only check existing STL file the same way as --validate does")
+                .arg(
+                    Arg::new("filename")
+                        .help("Filename of STL file to be checked")
+                        .required(true)
+                        .value_name("STLFILE")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .index(1),
+                ),
+        )
+        // Checking existing STL file needs no roller dimensions
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .disable_help_subcommand(true)
+}
+
+fn parse_transparent_level(value: &str) -> Result<TransparentLevel, String> {
+    match value {
+        "surface" => Ok(TransparentLevel::Surface),
+        "bottom" => Ok(TransparentLevel::Bottom),
+        _ => value
+            .parse::<f64>()
+            .map(TransparentLevel::Depth)
+            .map_err(|_| String::from("expected 'surface', 'bottom' or depth value")),
+    }
+}
+
+fn parse_height_source(value: &str) -> Result<HeightSource, String> {
+    match value {
+        "luma" => Ok(HeightSource::Luma),
+        "red" => Ok(HeightSource::Red),
+        "green" => Ok(HeightSource::Green),
+        "blue" => Ok(HeightSource::Blue),
+        "alpha" => Ok(HeightSource::Alpha),
+        "value" => Ok(HeightSource::Value),
+        "saturation" => Ok(HeightSource::Saturation),
+        _ => {
+            let weights = value
+                .strip_prefix("mix:")
+                .ok_or_else(|| String::from("unknown height source"))?
+                .split(',')
+                .map(|weight| weight.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|error| error.to_string())?;
+            match weights[..] {
+                [red, green, blue] if red != 0.0 || green != 0.0 || blue != 0.0 => {
+                    Ok(HeightSource::Mix(red, green, blue))
+                }
+                _ => Err(String::from(
+                    "expected three weights with at least one non-zero",
+                )),
+            }
+        }
+    }
+}
+
+fn parse_number_list(value: &str, separator: char) -> Result<Vec<f64>, String> {
+    value
+        .split(separator)
+        .map(|number| number.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|error| error.to_string())
+}
+
+fn parse_grid_step(value: &str) -> Result<(f64, f64), String> {
+    match parse_number_list(value, ',')?[..] {
+        [step] if step > 0.0 => Ok((step, step)),
+        [circumferential, axial] if circumferential > 0.0 && axial > 0.0 => {
+            Ok((circumferential, axial))
+        }
+        _ => Err(String::from(
+            "expected one or two (circumferential and axial) steps greater than zero",
+        )),
+    }
+}
+
+fn parse_input_levels(value: &str) -> Result<(f64, f64), String> {
+    match parse_number_list(value, ',')?[..] {
+        [black, white] if black < white && white.is_finite() && black.is_finite() => {
+            Ok((black, white))
+        }
+        _ => Err(String::from("expected two levels with BLACK < WHITE")),
+    }
+}
+
+fn parse_crop(value: &str) -> Result<Crop, String> {
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|error| error.to_string())?;
+    match numbers[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(Crop {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(String::from(
+            "expected X,Y,WIDTH,HEIGHT with width and height greater than zero",
+        )),
+    }
+}
+
+fn parse_angle(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(angle) if angle.is_finite() => Ok(angle),
+        _ => Err(String::from("expected finite angle in degrees")),
+    }
+}
+
+fn parse_padding(value: &str) -> Result<Padding, String> {
+    let margins = parse_number_list(value, ',')?;
+    if margins
+        .iter()
+        .any(|margin| margin.is_nan() || *margin < 0.0)
+    {
+        return Err(String::from("margins should not be negative"));
+    }
+    let (top, right, bottom, left) = match margins[..] {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return Err(String::from("expected 1, 2 or 4 margins")),
+    };
+    Ok(Padding {
+        top,
+        right,
+        bottom,
+        left,
+    })
+}
+
+fn parse_raw_size(value: &str) -> Result<(u32, u32), String> {
+    const SIZE_ERROR: &str = "expected WIDTHxHEIGHT with both sizes greater than zero";
+    let (width, height) = value.split_once('x').ok_or(SIZE_ERROR)?;
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(String::from(SIZE_ERROR)),
+    }
+}
+
+fn parse_output_levels(value: &str) -> Result<(f64, f64), String> {
+    match parse_number_list(value, ',')?[..] {
+        [low, high] if (0.0..=1.0).contains(&low) && (0.0..=1.0).contains(&high) => Ok((low, high)),
+        _ => Err(String::from("expected two levels between 0 and 1")),
+    }
+}
+
+fn parse_gamma(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(gamma) if gamma.is_finite() && gamma > 0.0 => Ok(gamma),
+        _ => Err(String::from("expected finite gamma greater than zero")),
+    }
+}
+
+fn parse_contrast(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(contrast) if contrast.is_finite() && contrast >= 0.0 => Ok(contrast),
+        _ => Err(String::from("expected finite contrast not less than zero")),
+    }
+}
+
+fn parse_seam_threshold(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(threshold) if threshold.is_finite() && threshold >= 0.0 => Ok(threshold),
+        _ => Err(String::from("expected finite fraction not less than zero")),
+    }
+}
+
+fn parse_tone_curve(value: &str) -> Result<Vec<(f64, f64)>, String> {
+    let points = value
+        .split(',')
+        .map(|point| match parse_number_list(point, ':')?[..] {
+            [x, y] => Ok((x, y)),
+            _ => Err(String::from("expected points in X:Y format")),
+        })
+        .collect::<Result<Vec<(f64, f64)>, String>>()?;
+    check_curve_points(&points)?;
+    Ok(points)
+}
+
+fn parse_threshold(value: &str) -> Result<Threshold, String> {
+    if value == "otsu" {
+        return Ok(Threshold::Otsu);
+    }
+    if let Some(radius) = value.strip_prefix("adaptive") {
+        let radius = match radius.strip_prefix(':') {
+            Some(radius) => match radius.parse::<usize>() {
+                Ok(radius) if radius >= 1 => radius,
+                _ => return Err(String::from("expected adaptive radius of at least 1 pixel")),
+            },
+            None if radius.is_empty() => 8,
+            None => return Err(String::from("expected adaptive:RADIUS")),
+        };
+        return Ok(Threshold::Adaptive(radius));
+    }
+    match value.parse::<f64>() {
+        Ok(level) if (0.0..=1.0).contains(&level) => Ok(Threshold::Fixed(level)),
+        _ => Err(String::from(
+            "expected level from 0 to 1, otsu or adaptive:RADIUS",
+        )),
+    }
+}
+
+// Number of copies after last colon is optional, so filenames may contain colons
+fn parse_collage_part(value: &str) -> Result<(String, u32), String> {
+    let part = match value.rsplit_once(':') {
+        Some((filename, copies)) => match copies.parse::<u32>() {
+            Ok(0) => return Err(String::from("number of copies should be greater than zero")),
+            Ok(copies) => (filename, copies),
+            Err(_) => (value, 1),
+        },
+        None => (value, 1),
+    };
+    if part.0.is_empty() {
+        return Err(String::from("image filename should not be empty"));
+    }
+    Ok((String::from(part.0), part.1))
+}
+
+fn parse_layer(value: &str) -> Result<Layer, String> {
+    const LAYER_ERROR: &str =
+        "expected OPERATOR[:SCALE]:SOURCE with image:IMGFILE, pattern:PATTERN or text:TEXT source";
+    let (operator, rest) = value.split_once(':').ok_or(LAYER_ERROR)?;
+    let operator = match operator {
+        "max" => BlendOperator::Max,
+        "min" => BlendOperator::Min,
+        "add" => BlendOperator::Add,
+        "multiply" => BlendOperator::Multiply,
+        "mask" => BlendOperator::Mask,
+        _ => {
+            return Err(String::from(
+                "operator should be max, min, add, multiply or mask",
+            ))
+        }
+    };
+    let (scale, rest) = match rest.split_once(':') {
+        Some((scale, source)) if scale.parse::<f64>().is_ok() => {
+            (scale.parse::<f64>().unwrap(), source)
+        }
+        _ => (1.0, rest),
+    };
+    if !(scale.is_finite() && scale >= 0.0) {
+        return Err(String::from(
+            "layer scale should be finite and not negative",
+        ));
+    }
+    let source = match rest.split_once(':') {
+        Some(("image", filename)) if !filename.is_empty() => {
+            LayerSource::Image(String::from(filename))
+        }
+        Some(("pattern", pattern)) => LayerSource::Pattern(parse_pattern(pattern)?),
+        Some(("text", text)) if !text.is_empty() => LayerSource::Text(String::from(text)),
+        _ => return Err(String::from(LAYER_ERROR)),
+    };
+    Ok(Layer {
+        operator,
+        scale,
+        source,
+    })
+}
+
+fn parse_pattern(value: &str) -> Result<Pattern, String> {
+    let (name, arguments) = match value.split_once(':') {
+        Some((name, arguments)) => (name, parse_number_list(arguments, ':')?),
+        None => (value, Vec::new()),
+    };
+    match (name, &arguments[..]) {
+        (_, &[size, ..]) if size <= 0.0 => Err(String::from("sizes should be greater than zero")),
+        ("ribs", &[pitch]) => Ok(Pattern::Ribs { pitch, angle: 0.0 }),
+        ("ribs", &[pitch, angle]) => Ok(Pattern::Ribs { pitch, angle }),
+        ("knurl", &[pitch]) => Ok(Pattern::Knurl {
+            pitch,
+            angle: 30.0,
+        }),
+        ("knurl", &[pitch, angle]) => Ok(Pattern::Knurl { pitch, angle }),
+        ("dots", &[pitch]) => Ok(Pattern::Dots {
+            pitch,
+            diameter: 0.5 * pitch,
+        }),
+        ("dots", &[pitch, diameter]) if diameter > 0.0 => Ok(Pattern::Dots { pitch, diameter }),
+        ("noise", &[scale]) => Ok(Pattern::Noise { scale }),
+        ("voronoi", &[cell]) => Ok(Pattern::Voronoi { cell }),
+        _ => Err(String::from(
+            "expected ribs:PITCH[:ANGLE], knurl:PITCH[:ANGLE], dots:PITCH[:DIAMETER], noise:SCALE or voronoi:CELL",
+        )),
+    }
+}
+
+fn parse_filter(value: &str) -> Result<Filter, String> {
+    let (name, arguments) = match value.split_once(':') {
+        Some((name, arguments)) => (name, parse_number_list(arguments, ':')?),
+        None => (value, Vec::new()),
+    };
+    match (name, &arguments[..]) {
+        ("blur", &[sigma]) if sigma.is_finite() && sigma > 0.0 => Ok(Filter::Blur(sigma)),
+        ("sharpen", &[sigma]) if sigma.is_finite() && sigma > 0.0 => {
+            Ok(Filter::Sharpen(sigma, 1.0))
+        }
+        ("sharpen", &[sigma, amount]) if sigma.is_finite() && sigma > 0.0 && amount.is_finite() => {
+            Ok(Filter::Sharpen(sigma, amount))
+        }
+        ("median", &[radius]) if radius.is_finite() && radius >= 1.0 => {
+            Ok(Filter::Median(radius as usize))
+        }
+        ("sobel", &[]) => Ok(Filter::Sobel),
+        ("canny", &[]) => Ok(Filter::Canny(0.1, 0.3)),
+        ("canny", &[low, high]) if 0.0 <= low && low < high && high <= 1.0 => {
+            Ok(Filter::Canny(low, high))
+        }
+        _ => Err(String::from(
+            "expected blur:SIGMA, sharpen:SIGMA[:AMOUNT], median:RADIUS, sobel or canny[:LOW:HIGH]",
+        )),
+    }
 }
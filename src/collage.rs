@@ -0,0 +1,222 @@
+use crate::image::{is_float_image, HeightSource, InputImage};
+use crate::transform::keep_precision;
+use anyhow::Result;
+use image::imageops::replace;
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+#[derive(Clone, Copy)]
+pub enum Layout {
+    // Parts follow each other along roller length (from top), each fills circumference
+    Bands,
+    // Parts follow each other around circumference, each fills roller length
+    Segments,
+}
+
+// Copies of part image are placed across layout direction
+pub struct CollagePart {
+    image: InputImage,
+    copies: u32,
+}
+
+pub struct Collage {
+    layout: Layout,
+    parts: Vec<CollagePart>,
+    pixelated: bool,
+}
+
+impl Collage {
+    pub fn from_files<'a>(
+        layout: Layout,
+        parts: impl Iterator<Item = &'a (String, u32)>,
+        raw_size: Option<(u32, u32)>,
+        pixelated: bool,
+    ) -> Result<Collage> {
+        let parts = parts
+            .map(|(filename, copies)| {
+                Ok(CollagePart {
+                    image: InputImage::from_file(filename, raw_size)?,
+                    copies: *copies,
+                })
+            })
+            .collect::<Result<Vec<CollagePart>>>()?;
+        Ok(Collage {
+            layout,
+            parts,
+            pixelated,
+        })
+    }
+
+    // Part dimensions as (across, along) layout direction
+    fn oriented(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self.layout {
+            Layout::Bands => (width, height),
+            Layout::Segments => (height, width),
+        }
+    }
+
+    // Extent of part along layout direction relative to common extent across it
+    fn relative_extent(&self, part: &CollagePart) -> f64 {
+        let (across, along) = self.oriented(part.image.dimensions());
+        along as f64 / { (across * part.copies) as f64 }
+    }
+
+    // Finest part resolution is kept
+    pub fn dimensions(&self) -> (u32, u32) {
+        let across = self
+            .parts
+            .iter()
+            .map(|part| self.oriented(part.image.dimensions()).0 * part.copies)
+            .max()
+            .unwrap();
+        let extent: f64 = self.parts.iter().map(|p| self.relative_extent(p)).sum();
+        let along = ({ across as f64 } * extent).round() as u32;
+        self.oriented((across, along.max(1)))
+    }
+
+    // Alpha is used only if all parts are vector artwork, otherwise their coverage
+    // is converted to gray level to match raster parts
+    pub fn default_height_source(&self) -> HeightSource {
+        let vector_only = (self.parts.iter())
+            .all(|part| matches!(part.image.default_height_source(), HeightSource::Alpha));
+        if vector_only {
+            HeightSource::Alpha
+        } else {
+            HeightSource::Luma
+        }
+    }
+
+    // Float parts keep their values beyond unit range and full precision
+    fn has_float_part(&self) -> bool {
+        (self.parts.iter())
+            .any(|part| matches!(&part.image, InputImage::Raster(image) if is_float_image(image)))
+    }
+
+    pub fn render(&self, width: u32, height: u32) -> Result<DynamicImage> {
+        let (across, along) = self.oriented((width, height));
+        let total_extent: f64 = self.parts.iter().map(|p| self.relative_extent(p)).sum();
+        let coverage_to_gray = matches!(self.default_height_source(), HeightSource::Luma);
+        let mut canvas = ImageBuffer::from_pixel(width, height, Rgba([0f32, 0.0, 0.0, 0.0]));
+        let mut extent = 0.0;
+        for part in &self.parts {
+            let start = ({ along as f64 } * extent / total_extent).round() as u32;
+            extent += self.relative_extent(part);
+            let end = ({ along as f64 } * extent / total_extent).round() as u32;
+            for copy in 0..part.copies {
+                let copy_start = { across * copy } / part.copies;
+                let copy_end = { across * (copy + 1) } / part.copies;
+                if end == start || copy_end == copy_start {
+                    continue;
+                }
+                let (copy_width, copy_height) = self.oriented((copy_end - copy_start, end - start));
+                let mut rendered = part.image.render(copy_width, copy_height, self.pixelated)?;
+                if coverage_to_gray
+                    && matches!(part.image.default_height_source(), HeightSource::Alpha)
+                {
+                    rendered = coverage_image(rendered);
+                }
+                let (x, y) = self.oriented((copy_start, start));
+                replace(&mut canvas, &rendered.into_rgba32f(), x as i64, y as i64);
+            }
+        }
+        let image = DynamicImage::ImageRgba32F(canvas);
+        Ok(keep_precision(image, self.has_float_part()))
+    }
+}
+
+fn coverage_image(image: DynamicImage) -> DynamicImage {
+    let mut image = image.into_rgba16();
+    for pixel in image.pixels_mut() {
+        *pixel = Rgba([pixel[3], pixel[3], pixel[3], u16::MAX]);
+    }
+    DynamicImage::ImageRgba16(image)
+}
+
+#[cfg(test)]
+#[test]
+fn test_collage_bands() {
+    let band = |value: u16, width: u32, height: u32| CollagePart {
+        image: InputImage::Raster(DynamicImage::ImageLuma16(ImageBuffer::from_pixel(
+            width,
+            height,
+            image::Luma([value]),
+        ))),
+        copies: 1,
+    };
+    let collage = Collage {
+        layout: Layout::Bands,
+        parts: vec![band(0, 4, 1), band(u16::MAX, 2, 1), band(0, 8, 2)],
+        pixelated: true,
+    };
+    assert_eq!(collage.dimensions(), (8, 8));
+    let image = collage.render(4, 4).unwrap().into_rgba16();
+    let column: Vec<u16> = (0..4).map(|y| image.get_pixel(1, y)[0]).collect();
+    assert_eq!(column, vec![0, u16::MAX, u16::MAX, 0]);
+    let float_band = CollagePart {
+        image: InputImage::Raster(DynamicImage::ImageRgb32F(ImageBuffer::from_pixel(
+            4,
+            1,
+            image::Rgb([2.5f32, 2.5, 2.5]),
+        ))),
+        copies: 1,
+    };
+    let collage = Collage {
+        layout: Layout::Bands,
+        parts: vec![band(0, 4, 1), float_band],
+        pixelated: true,
+    };
+    let image = collage.render(4, 2).unwrap();
+    assert!(is_float_image(&image));
+    assert_eq!(image.into_rgba32f().get_pixel(0, 1)[0], 2.5);
+}
+
+#[test]
+fn test_collage_vector_parts() {
+    let filename = std::env::temp_dir().join(format!("img2roller_{}.svg", std::process::id()));
+    let data = concat!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2">"#,
+        r#"<rect x="0" y="0" width="2" height="2" fill="black"/></svg>"#
+    );
+    std::fs::write(&filename, data).unwrap();
+    let svg = || CollagePart {
+        image: InputImage::from_file(filename.to_str().unwrap(), None).unwrap(),
+        copies: 1,
+    };
+    let collage = Collage {
+        layout: Layout::Bands,
+        parts: vec![svg(), svg()],
+        pixelated: false,
+    };
+    // Uncovered area stays transparent
+    assert!(matches!(
+        collage.default_height_source(),
+        HeightSource::Alpha
+    ));
+    let image = collage.render(4, 4).unwrap().into_rgba16();
+    assert_eq!(
+        (image.get_pixel(0, 0)[3], image.get_pixel(3, 3)[3]),
+        (u16::MAX, 0)
+    );
+    let raster = CollagePart {
+        image: InputImage::Raster(DynamicImage::ImageLuma16(ImageBuffer::from_pixel(
+            4,
+            2,
+            image::Luma([u16::MAX / 2]),
+        ))),
+        copies: 1,
+    };
+    let collage = Collage {
+        layout: Layout::Bands,
+        parts: vec![svg(), raster],
+        pixelated: false,
+    };
+    std::fs::remove_file(&filename).unwrap();
+    // Coverage of vector part becomes gray level next to raster part
+    assert!(matches!(
+        collage.default_height_source(),
+        HeightSource::Luma
+    ));
+    let image = collage.render(4, 4).unwrap().into_luma16();
+    let row: Vec<u16> = (0..4).map(|x| image.get_pixel(x, 0)[0]).collect();
+    assert_eq!(row, vec![u16::MAX, u16::MAX, 0, 0]);
+    assert_eq!(image.get_pixel(3, 3)[0], u16::MAX / 2);
+}
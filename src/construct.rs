@@ -5,11 +5,14 @@ use crate::split::split_quad_optimal;
 use crate::stl::STLFileWriter;
 use crate::vectors::Vector3;
 use anyhow::Result;
+use std::f64::consts::TAU;
 
 pub fn make_pattern_roller(params: &Parameters, mut stl_writer: STLFileWriter) -> Result<()> {
-    let big_circle = CircleConverter::new(
+    let phi_step = TAU / { params.surface_width() as f64 };
+    let big_circle = CircleConverter::with_angles(
         params.circle_points() as usize,
-        params.roller_diameter * 0.5,
+        params.surface_radius(),
+        |n| params.vertex_position(n) * phi_step,
     );
     make_cylinder_patterned(&mut stl_writer, &params, &big_circle)?;
     match params.roller_end {
@@ -20,7 +23,7 @@ pub fn make_pattern_roller(params: &Parameters, mut stl_writer: STLFileWriter) -
             pin_length,
         } => {
             let small_circle =
-                CircleConverter::new(circle_points as usize, params.roller_diameter * 0.5);
+                CircleConverter::new(circle_points as usize, params.surface_radius());
             make_pins(
                 &mut stl_writer,
                 &params,
@@ -42,7 +45,7 @@ pub fn make_pattern_roller(params: &Parameters, mut stl_writer: STLFileWriter) -
             channel_diameter,
         } => {
             let small_circle =
-                CircleConverter::new(circle_points as usize, params.roller_diameter * 0.5);
+                CircleConverter::new(circle_points as usize, params.surface_radius());
             make_channel(&mut stl_writer, &params, &small_circle, channel_diameter)?;
             make_lids_holed(
                 &mut stl_writer,
@@ -61,44 +64,51 @@ fn make_cylinder_patterned(
     params: &Parameters,
     circle: &CircleConverter,
 ) -> Result<()> {
-    let width = params.image_width as usize;
-    let height = params.image_height as usize;
-    let hstack = params.stack_horizontal as usize;
-    let vstack = params.stack_vertical as usize;
+    let width = params.vertex_columns();
+    let height = params.vertex_rows();
     let z_max = match params.roller_end {
         RollerEnd::Flat => params.roller_length,
         RollerEnd::Channel { .. } => params.roller_length,
         RollerEnd::Pin { pin_length, .. } => params.roller_length + pin_length,
     };
-    let z_step =
-        params.roller_length / { (params.image_height * params.stack_vertical - 1) as f64 };
+    let z_step = params.roller_length / { (params.surface_height() - 1) as f64 };
+    let z_values: Vec<f64> = (0..height)
+        .map(|j| z_max - (params.vertex_position(j) * z_step).clamp(0.0, params.roller_length))
+        .collect();
+    if let Some(mesh) = &params.adaptive_mesh {
+        let point = |(i, j): (usize, usize)| {
+            circle.get_vector3(i, params.get_vertex_rho(i % width, j), z_values[j])
+        };
+        for [a, b, c] in &mesh.triangles {
+            stl_writer.write_face_auto_normal(&point(*a), &point(*b), &point(*c))?;
+        }
+        return Ok(());
+    }
     for i in 0..width {
-        for j in 0..height {
+        for j in 0..height - 1 {
             let (tlbr_split, rho_tl, rho_tr, rho_bl, rho_br) = split_quad_optimal(params, i, j);
-            for p in 0..hstack {
-                let (x_tl, y_tl) = circle.get_xy(i + p * width, rho_tl);
-                let (x_tr, y_tr) = circle.get_xy(i + p * width + 1, rho_tr);
-                let (x_bl, y_bl) = circle.get_xy(i + p * width, rho_bl);
-                let (x_br, y_br) = circle.get_xy(i + p * width + 1, rho_br);
-                for q in 0..vstack {
-                    if j == height - 1 && q == vstack - 1 {
-                        continue;
-                    };
-                    let z_t = z_max - { (j + height * q) as f64 } * z_step;
-                    let z_b = z_t - z_step;
-                    let point_tl = Vector3::new(x_tl, y_tl, z_t);
-                    let point_bl = Vector3::new(x_bl, y_bl, z_b);
-                    let point_tr = Vector3::new(x_tr, y_tr, z_t);
-                    let point_br = Vector3::new(x_br, y_br, z_b);
-                    if tlbr_split {
-                        stl_writer.write_face_auto_normal(&point_tl, &point_br, &point_tr)?;
-                        stl_writer.write_face_auto_normal(&point_bl, &point_br, &point_tl)?;
-                    } else {
-                        stl_writer.write_face_auto_normal(&point_bl, &point_tr, &point_tl)?;
-                        stl_writer.write_face_auto_normal(&point_bl, &point_br, &point_tr)?;
-                    };
-                }
-            }
+            let (z_t, z_b) = (z_values[j], z_values[j + 1]);
+            let (point_tl, point_tr, point_bl, point_br) = match &params.normal_displacement {
+                None => (
+                    circle.get_vector3(i, rho_tl, z_t),
+                    circle.get_vector3(i + 1, rho_tr, z_t),
+                    circle.get_vector3(i, rho_bl, z_b),
+                    circle.get_vector3(i + 1, rho_br, z_b),
+                ),
+                Some(displacement) => (
+                    displacement.get_vector3(circle, i, j, z_t),
+                    displacement.get_vector3(circle, i + 1, j, z_t),
+                    displacement.get_vector3(circle, i, j + 1, z_b),
+                    displacement.get_vector3(circle, i + 1, j + 1, z_b),
+                ),
+            };
+            if tlbr_split {
+                stl_writer.write_face_auto_normal(&point_tl, &point_br, &point_tr)?;
+                stl_writer.write_face_auto_normal(&point_bl, &point_br, &point_tl)?;
+            } else {
+                stl_writer.write_face_auto_normal(&point_bl, &point_tr, &point_tl)?;
+                stl_writer.write_face_auto_normal(&point_bl, &point_br, &point_tr)?;
+            };
         }
     }
     Ok(())
@@ -110,8 +120,8 @@ fn make_lids_solid(
     circle: CircleConverter,
 ) -> Result<()> {
     let z_max = params.roller_length;
-    let top_radii = params.get_image_topline();
-    let bot_radii = params.get_image_botline();
+    let top_radii = params.get_surface_topline();
+    let bot_radii = params.get_surface_botline();
     let mut top_point_old: Vector3;
     let mut bot_point_old: Vector3;
     let top_center = Vector3::new(circle.axis_shift, circle.axis_shift, z_max);
@@ -201,8 +211,8 @@ fn make_lids_holed(
     inner_dimaeter: f64,
     z_shift: f64,
 ) -> Result<()> {
-    let radii_top = params.get_image_topline();
-    let radii_bot = params.get_image_botline();
+    let radii_top = params.get_surface_topline();
+    let radii_bot = params.get_surface_botline();
     let z_top = z_shift + params.roller_length;
     let z_bot = z_shift;
     let inner_radius = inner_dimaeter * 0.5;
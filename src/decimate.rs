@@ -0,0 +1,378 @@
+use crate::parameters::Parameters;
+use crate::split::split_quad_optimal;
+use std::f64::consts::{PI, TAU};
+
+// Largest block side (in vertex grid cells) considered for merging
+const MAX_BLOCK_SIZE: usize = 64;
+
+// Rectangle of vertex grid cells, columns wrap around circumference
+// (column equal to vertex columns count is the first one)
+#[derive(Clone, Copy)]
+struct Block {
+    i0: usize,
+    i1: usize,
+    j0: usize,
+    j1: usize,
+}
+
+impl Block {
+    fn corners(&self) -> [(usize, usize); 4] {
+        [
+            (self.i0, self.j0),
+            (self.i1, self.j0),
+            (self.i1, self.j1),
+            (self.i0, self.j1),
+        ]
+    }
+
+    // Blocks one cell wide or tall have no interior vertices and are stitched between long sides
+    fn is_strip(&self) -> bool {
+        self.i1 - self.i0 == 1 || self.j1 - self.j0 == 1
+    }
+}
+
+// Surface triangulated with large fans over flat blocks and regular quads elsewhere,
+// triangles are given by (column, row) of vertex grid
+pub struct AdaptiveMesh {
+    pub triangles: Vec<[(usize, usize); 3]>,
+}
+
+impl AdaptiveMesh {
+    // Merged blocks deviate from surface through all vertices by less than tolerance
+    pub fn new(params: &Parameters, tolerance: f64) -> AdaptiveMesh {
+        let (columns, rows) = (params.vertex_columns(), params.vertex_rows());
+        let mut merged = Vec::new();
+        let mut cells = Vec::new();
+        for j0 in (0..rows - 1).step_by(MAX_BLOCK_SIZE) {
+            for i0 in (0..columns).step_by(MAX_BLOCK_SIZE) {
+                let block = Block {
+                    i0,
+                    i1: (i0 + MAX_BLOCK_SIZE).min(columns),
+                    j0,
+                    j1: (j0 + MAX_BLOCK_SIZE).min(rows - 1),
+                };
+                subdivide(params, block, tolerance, &mut merged, &mut cells);
+            }
+        }
+        // Vertices on block edges are shared with all neighbours, so that there are no T-junctions,
+        // end rows are kept complete for lids
+        let mut used = vec![false; columns * rows];
+        for block in merged.iter().chain(cells.iter()) {
+            for (i, j) in block.corners() {
+                used[j * columns + i % columns] = true;
+            }
+        }
+        for i in 0..columns {
+            used[i] = true;
+            used[(rows - 1) * columns + i] = true;
+        }
+        let is_used = |&(i, j): &(usize, usize)| used[j * columns + i % columns];
+        let mut triangles = Vec::with_capacity(2 * cells.len() + 8 * merged.len());
+        for block in merged {
+            if block.is_strip() {
+                stitch_strip(block, is_used, &mut triangles);
+                continue;
+            }
+            let mut boundary: Vec<(usize, usize)> = Vec::new();
+            boundary.extend((block.i0..block.i1).map(|i| (i, block.j0)));
+            boundary.extend((block.j0..block.j1).map(|j| (block.i1, j)));
+            boundary.extend((block.i0 + 1..=block.i1).rev().map(|i| (i, block.j1)));
+            boundary.extend((block.j0 + 1..=block.j1).rev().map(|j| (block.i0, j)));
+            boundary.retain(is_used);
+            let center = ((block.i0 + block.i1) / 2, (block.j0 + block.j1) / 2);
+            for (n, &point) in boundary.iter().enumerate() {
+                let next = boundary[(n + 1) % boundary.len()];
+                triangles.push([center, next, point]);
+            }
+        }
+        for Block { i0: i, j0: j, .. } in cells {
+            let (tl, tr, bl, br) = ((i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1));
+            let (tlbr_split, ..) = split_quad_optimal(params, i, j);
+            if tlbr_split {
+                triangles.push([tl, br, tr]);
+                triangles.push([bl, br, tl]);
+            } else {
+                triangles.push([bl, tr, tl]);
+                triangles.push([bl, br, tr]);
+            }
+        }
+        AdaptiveMesh { triangles }
+    }
+}
+
+// Used vertices of both long sides of strip are joined by zigzag of triangles
+fn stitch_strip(
+    block: Block,
+    is_used: impl Fn(&(usize, usize)) -> bool,
+    triangles: &mut Vec<[(usize, usize); 3]>,
+) {
+    let vertical = block.i1 - block.i0 == 1;
+    // Sides are listed along strip, first one is left (vertical strip) or top side
+    let side = |offset: usize| -> Vec<(usize, usize)> {
+        let points: Vec<(usize, usize)> = if vertical {
+            (block.j0..=block.j1)
+                .map(|j| (block.i0 + offset, j))
+                .collect()
+        } else {
+            (block.i0..=block.i1)
+                .map(|i| (i, block.j0 + offset))
+                .collect()
+        };
+        points.into_iter().filter(&is_used).collect()
+    };
+    let (first, second) = (side(0), side(1));
+    let along = |(i, j): (usize, usize)| if vertical { j } else { i };
+    let (mut a, mut b) = (0, 0);
+    while a + 1 < first.len() || b + 1 < second.len() {
+        let advance_second = a + 1 == first.len()
+            || (b + 1 < second.len() && along(second[b + 1]) <= along(first[a + 1]));
+        // Orientation is the same as of regular quads, transposed for horizontal strips
+        let triangle = match (advance_second, vertical) {
+            (true, true) => [first[a], second[b + 1], second[b]],
+            (false, true) => [first[a], first[a + 1], second[b]],
+            (true, false) => [first[a], second[b], second[b + 1]],
+            (false, false) => [first[a], second[b], first[a + 1]],
+        };
+        triangles.push(triangle);
+        if advance_second {
+            b += 1;
+        } else {
+            a += 1;
+        }
+    }
+}
+
+// Blocks which are not flat are split in halves by cut across direction of larger curvature
+// (or across longer side), so that long strips along either direction can be merged
+fn subdivide(
+    params: &Parameters,
+    block: Block,
+    tolerance: f64,
+    merged: &mut Vec<Block>,
+    cells: &mut Vec<Block>,
+) {
+    let (width, height) = (block.i1 - block.i0, block.j1 - block.j0);
+    if width == 1 && height == 1 {
+        cells.push(block);
+        return;
+    }
+    if is_flat(params, block, tolerance) {
+        merged.push(block);
+        return;
+    }
+    let split_columns = if width == 1 || height == 1 {
+        height == 1
+    } else {
+        let (curvature_i, curvature_j) = curvature(params, block);
+        if curvature_i == curvature_j {
+            width >= height
+        } else {
+            curvature_i > curvature_j
+        }
+    };
+    let halves = if split_columns {
+        let middle = block.i0 + width / 2;
+        [
+            Block {
+                i1: middle,
+                ..block
+            },
+            Block {
+                i0: middle,
+                ..block
+            },
+        ]
+    } else {
+        let middle = block.j0 + height / 2;
+        [
+            Block {
+                j1: middle,
+                ..block
+            },
+            Block {
+                j0: middle,
+                ..block
+            },
+        ]
+    };
+    for half in halves {
+        subdivide(params, half, tolerance, merged, cells);
+    }
+}
+
+// Largest second differences of radii within block along columns and rows
+fn curvature(params: &Parameters, block: Block) -> (f64, f64) {
+    let columns = params.vertex_columns();
+    let rho = |i: usize, j: usize| params.get_vertex_rho(i % columns, j);
+    let (mut curvature_i, mut curvature_j) = (0.0f64, 0.0f64);
+    for j in block.j0..=block.j1 {
+        for i in block.i0..=block.i1 {
+            if i > block.i0 && i < block.i1 {
+                let second = rho(i - 1, j) - 2.0 * rho(i, j) + rho(i + 1, j);
+                curvature_i = curvature_i.max(second.abs());
+            }
+            if j > block.j0 && j < block.j1 {
+                let second = rho(i, j - 1) - 2.0 * rho(i, j) + rho(i, j + 1);
+                curvature_j = curvature_j.max(second.abs());
+            }
+        }
+    }
+    (curvature_i, curvature_j)
+}
+
+// Radii are compared with coarsest triangulation of block (fan over corners or two sides
+// of strip). Merged triangles interpolate vertices deviating by up to same amount, so
+// half of tolerance left after chord sagitta is allowed for vertices.
+fn is_flat(params: &Parameters, block: Block, tolerance: f64) -> bool {
+    let columns = params.vertex_columns();
+    let rho = |i: usize, j: usize| params.get_vertex_rho(i % columns, j);
+    let point = |(i, j): (usize, usize)| (params.vertex_position(i), params.vertex_position(j));
+    let rho_max = (block.j0..=block.j1)
+        .flat_map(|j| (block.i0..=block.i1).map(move |i| (i, j)))
+        .map(|(i, j)| rho(i, j))
+        .fold(f64::MIN, f64::max);
+    // Sagitta of chord spanning whole block around circumference
+    let width = point((block.i1, 0)).0 - point((block.i0, 0)).0;
+    let angle = TAU * width / { params.surface_width() as f64 };
+    // Fan over block spanning half of circumference or more would fold through roller axis
+    if angle >= PI {
+        return false;
+    }
+    let allowed = 0.5 * (tolerance - rho_max * (1.0 - (0.5 * angle).cos()));
+    if allowed < 0.0 {
+        return false;
+    }
+    let reference = |i: usize, j: usize| -> f64 {
+        let (x, y) = point((i, j));
+        if block.is_strip() {
+            // Linear along side between its corners
+            let ((x0, y0), (x1, y1)) = (point((block.i0, block.j0)), point((block.i1, block.j1)));
+            if block.i1 - block.i0 == 1 {
+                let t = (y - y0) / (y1 - y0);
+                rho(i, block.j0) + t * (rho(i, block.j1) - rho(i, block.j0))
+            } else {
+                let t = (x - x0) / (x1 - x0);
+                rho(block.i0, j) + t * (rho(block.i1, j) - rho(block.i0, j))
+            }
+        } else {
+            let center = ((block.i0 + block.i1) / 2, (block.j0 + block.j1) / 2);
+            let corners = block.corners();
+            (0..4)
+                .find_map(|n| {
+                    interpolate(
+                        [center, corners[n], corners[(n + 1) % 4]]
+                            .map(|v| (point(v), rho(v.0, v.1))),
+                        (x, y),
+                    )
+                })
+                .unwrap_or_else(|| rho(i, j))
+        }
+    };
+    (block.j0..=block.j1)
+        .all(|j| (block.i0..=block.i1).all(|i| (rho(i, j) - reference(i, j)).abs() <= allowed))
+}
+
+// Linear interpolation over triangle given by ((x, y), value) vertices,
+// None if point is outside of it
+fn interpolate(vertices: [((f64, f64), f64); 3], (x, y): (f64, f64)) -> Option<f64> {
+    const MARGIN: f64 = 1e-9;
+    let [((xa, ya), va), ((xb, yb), vb), ((xc, yc), vc)] = vertices;
+    let determinant = (xb - xa) * (yc - ya) - (xc - xa) * (yb - ya);
+    let u = ((x - xa) * (yc - ya) - (xc - xa) * (y - ya)) / determinant;
+    let v = ((xb - xa) * (y - ya) - (x - xa) * (yb - ya)) / determinant;
+    if u < -MARGIN || v < -MARGIN || u + v > 1.0 + MARGIN {
+        return None;
+    }
+    Some(va + u * (vb - va) + v * (vc - va))
+}
+
+#[cfg(test)]
+use crate::parameters::test_cli_arguments_with_pixels;
+#[cfg(test)]
+use image::Luma;
+
+#[cfg(test)]
+fn bump_parameters(tolerance: f64) -> Parameters {
+    // Flat image with single bump
+    let arguments = format!(
+        "img2roller -d 2 -e 0.5 --adaptive-mesh {} test.png",
+        tolerance
+    );
+    test_cli_arguments_with_pixels(&arguments, (16, 8), |x, y| {
+        Luma([if (x, y) == (5, 3) { u16::MAX } else { 0 }])
+    })
+    .unwrap()
+}
+
+// Problems of surface welded on cylinder, except open edges at roller ends
+#[cfg(test)]
+fn surface_problems(params: &Parameters) -> Vec<crate::validate::Problem> {
+    use crate::validate::{Mesh, ProblemKind};
+    let columns = params.vertex_columns();
+    let last_row = params.vertex_rows() - 1;
+    let point = |(i, j): (usize, usize)| {
+        let phi = TAU * params.vertex_position(i) / { params.surface_width() as f64 };
+        let rho = params.get_vertex_rho(i % columns, j);
+        [rho * phi.cos(), rho * phi.sin(), params.vertex_position(j)]
+    };
+    let mesh = params.adaptive_mesh.as_ref().unwrap();
+    let triangles: Vec<[[f64; 3]; 3]> = (mesh.triangles.iter())
+        .map(|triangle| triangle.map(point))
+        .collect();
+    let ends = [0.0, params.vertex_position(last_row)];
+    Mesh::from_triangles(&triangles)
+        .find_problems()
+        .into_iter()
+        .filter(|p| p.kind != ProblemKind::OpenEdge || !ends.contains(&p.position[2]))
+        .collect()
+}
+
+#[test]
+fn test_adaptive_mesh() {
+    let parameters = bump_parameters(0.5);
+    let mesh = parameters.adaptive_mesh.as_ref().unwrap();
+    assert!(mesh.triangles.len() < 2 * 16 * 7);
+    // Triangles cover whole surface once with the same orientation
+    let signed_area = |[a, b, c]: [(usize, usize); 3]| {
+        let (ax, ay) = (a.0 as f64, a.1 as f64);
+        ((b.0 as f64 - ax) * (c.1 as f64 - ay) - (c.0 as f64 - ax) * (b.1 as f64 - ay)) * 0.5
+    };
+    assert!(mesh.triangles.iter().all(|t| signed_area(*t) < 0.0));
+    let total_area: f64 = mesh.triangles.iter().map(|t| signed_area(*t)).sum();
+    assert_eq!(total_area, -16.0 * 7.0);
+    // Shared block edges have no T-junctions
+    assert!(surface_problems(&parameters).is_empty());
+}
+
+#[test]
+fn test_huge_tolerance() {
+    // Blocks are limited by angle even when any deviation is tolerated
+    for tolerance in [100.0, 1e6, f64::INFINITY] {
+        let parameters = bump_parameters(tolerance);
+        assert!(surface_problems(&parameters).is_empty());
+    }
+}
+
+#[test]
+fn test_axial_strips() {
+    // Ridges along axis are merged into strips one cell wide
+    let arguments = "img2roller -d 2 -e 0.5 --adaptive-mesh 0.1 test.png";
+    let parameters = test_cli_arguments_with_pixels(arguments, (16, 8), |x, _| {
+        Luma([if x % 2 == 0 { 0 } else { u16::MAX }])
+    })
+    .unwrap();
+    let mesh = parameters.adaptive_mesh.as_ref().unwrap();
+    assert_eq!(mesh.triangles.len(), 2 * 16);
+    assert!(surface_problems(&parameters).is_empty());
+}
+
+#[test]
+fn test_is_flat() {
+    let parameters = bump_parameters(0.1);
+    let block = |i0, i1, j0, j1| Block { i0, i1, j0, j1 };
+    assert!(!is_flat(&parameters, block(4, 8, 2, 6), 0.1));
+    assert!(!is_flat(&parameters, block(5, 6, 0, 7), 0.1));
+    assert!(is_flat(&parameters, block(8, 10, 2, 6), 0.1));
+    // Chord spanning large angle deviates from cylinder
+    assert!(!is_flat(&parameters, block(8, 16, 4, 7), 0.1));
+}
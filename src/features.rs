@@ -0,0 +1,260 @@
+use crate::plane::{Plane, Stacking};
+
+#[derive(Clone, Copy)]
+pub enum FeatureAction {
+    Report,
+    Remove,
+}
+
+impl FeatureAction {
+    pub fn from_name(name: Option<&String>) -> FeatureAction {
+        match name.map(String::as_str) {
+            Some("remove") => FeatureAction::Remove,
+            _ => FeatureAction::Report,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureKind {
+    Ridge,
+    Groove,
+}
+
+// Connected group of pixels deviating from opened (ridge) or closed (groove) relief,
+// located by its pixel with largest deviation
+pub struct ThinFeature {
+    pub kind: FeatureKind,
+    pub column: usize,
+    pub row: usize,
+    pub pixels: usize,
+    pub deviation: f64,
+}
+
+impl Plane {
+    // Minimum or maximum over ellipse of given (horizontal, vertical) radii
+    fn extremum(&self, radius: (usize, usize), pick: fn(f64, f64) -> f64) -> Plane {
+        let (rx, ry) = (radius.0 as isize, radius.1 as isize);
+        let offsets: Vec<(isize, isize)> = (-ry..=ry)
+            .flat_map(|dj| (-rx..=rx).map(move |di| (di, dj)))
+            .filter(|(di, dj)| {
+                di * di * ry * ry + dj * dj * rx * rx <= rx * rx * ry * ry + rx * ry * (rx + ry) / 2
+            })
+            .collect();
+        self.map_pixels(|i, j| {
+            offsets
+                .iter()
+                .map(|(di, dj)| self.get(i + di, j + dj))
+                .reduce(pick)
+                .unwrap()
+        })
+    }
+
+    // Removes ridges narrower than element diameter
+    fn opening(&self, radius: (usize, usize)) -> Plane {
+        self.extremum(radius, f64::min).extremum(radius, f64::max)
+    }
+
+    // Removes grooves narrower than element diameter
+    fn closing(&self, radius: (usize, usize)) -> Plane {
+        self.extremum(radius, f64::max).extremum(radius, f64::min)
+    }
+}
+
+// Radius of disk element in pixels removing features narrower than given width in pixels
+pub fn element_radius(feature_width: f64) -> usize {
+    ((feature_width - 1.0) * 0.5).ceil().max(0.0) as usize
+}
+
+pub fn find_thin_features(
+    radii: &[f64],
+    width: usize,
+    height: usize,
+    stacking: Stacking,
+    radius: (usize, usize),
+    tolerance: f64,
+) -> Vec<ThinFeature> {
+    let plane = Plane {
+        width,
+        height,
+        stacking,
+        values: radii.to_vec(),
+    };
+    let ridges: Vec<f64> = (plane.opening(radius).values.iter())
+        .zip(radii)
+        .map(|(opened, rho)| rho - opened)
+        .collect();
+    let grooves: Vec<f64> = (plane.closing(radius).values.iter())
+        .zip(radii)
+        .map(|(closed, rho)| closed - rho)
+        .collect();
+    let mut features = group_pixels(&ridges, &plane, tolerance, FeatureKind::Ridge);
+    features.extend(group_pixels(
+        &grooves,
+        &plane,
+        tolerance,
+        FeatureKind::Groove,
+    ));
+    features
+}
+
+pub fn remove_thin_features(
+    radii: &mut [f64],
+    width: usize,
+    height: usize,
+    stacking: Stacking,
+    radius: (usize, usize),
+) {
+    let plane = Plane {
+        width,
+        height,
+        stacking,
+        values: radii.to_vec(),
+    };
+    radii.copy_from_slice(&plane.opening(radius).closing(radius).values);
+}
+
+pub fn report_thin_features(
+    features: &[ThinFeature],
+    min_feature: f64,
+    grid_step: (f64, f64),
+    removed: bool,
+) {
+    const LISTED_FEATURES: usize = 10;
+    if features.is_empty() {
+        return;
+    }
+    if removed {
+        eprintln!(
+            "note: Removed {} relief features narrower than {}",
+            features.len(),
+            min_feature
+        );
+        return;
+    }
+    eprintln!(
+        "warning: Found {} relief features narrower than {}:",
+        features.len(),
+        min_feature
+    );
+    for feature in features.iter().take(LISTED_FEATURES) {
+        eprintln!(
+            "  {} at {:.1} around circumference, {:.1} from top edge ({} pixels, height {:.3})",
+            match feature.kind {
+                FeatureKind::Ridge => "ridge",
+                FeatureKind::Groove => "groove",
+            },
+            ({ feature.column as f64 } + 0.5) * grid_step.0,
+            ({ feature.row as f64 } + 0.5) * grid_step.1,
+            feature.pixels,
+            feature.deviation
+        );
+    }
+    if features.len() > LISTED_FEATURES {
+        eprintln!("  ... and {} more", features.len() - LISTED_FEATURES);
+    }
+}
+
+// Pixels with deviation above tolerance are grouped using 8-connectivity
+fn group_pixels(
+    deviations: &[f64],
+    plane: &Plane,
+    tolerance: f64,
+    kind: FeatureKind,
+) -> Vec<ThinFeature> {
+    let mut visited: Vec<bool> = deviations.iter().map(|d| *d <= tolerance).collect();
+    let mut features = Vec::new();
+    for start in 0..deviations.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut feature = ThinFeature {
+            kind,
+            column: start % plane.width,
+            row: start / plane.width,
+            pixels: 0,
+            deviation: deviations[start],
+        };
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            feature.pixels += 1;
+            if deviations[n] > feature.deviation {
+                feature.column = n % plane.width;
+                feature.row = n / plane.width;
+                feature.deviation = deviations[n];
+            }
+            let (i, j) = ((n % plane.width) as isize, (n / plane.width) as isize);
+            for (di, dj) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let n_next = match plane.index(i + di, j + dj) {
+                    Some(n_next) => n_next,
+                    None => continue,
+                };
+                if !visited[n_next] {
+                    visited[n_next] = true;
+                    stack.push(n_next);
+                }
+            }
+        }
+        features.push(feature);
+    }
+    features
+}
+
+#[cfg(test)]
+use crate::parameters::StackRule;
+
+#[cfg(test)]
+const TILE: Stacking = Stacking {
+    rules: (StackRule::Repeat, StackRule::Repeat),
+    copies: (1, 2),
+};
+
+#[test]
+fn test_element_radius() {
+    assert_eq!(element_radius(1.0), 0);
+    assert_eq!(element_radius(2.5), 1);
+    assert_eq!(element_radius(3.0), 1);
+    assert_eq!(element_radius(3.2), 2);
+}
+
+#[test]
+fn test_thin_features() {
+    // One pixel wide ridge and three pixels wide groove across 8x8 tile
+    let mut radii = vec![1.0; 64];
+    for j in 0..8 {
+        radii[j * 8 + 1] = 2.0;
+        for i in 4..7 {
+            radii[j * 8 + i] = 0.0;
+        }
+    }
+    let features = find_thin_features(&radii, 8, 8, TILE, (1, 1), 0.5);
+    assert_eq!(features.len(), 1);
+    assert_eq!(features[0].kind, FeatureKind::Ridge);
+    assert_eq!((features[0].column, features[0].pixels), (1, 8));
+    let features = find_thin_features(&radii, 8, 8, TILE, (2, 2), 0.5);
+    assert_eq!(features.len(), 2);
+    assert_eq!(features[1].kind, FeatureKind::Groove);
+    remove_thin_features(&mut radii, 8, 8, TILE, (2, 2));
+    assert_eq!(radii, vec![1.0; 64]);
+}
+
+#[test]
+fn test_elliptic_element() {
+    // Vertical ridge is removed by horizontal element only
+    let mut radii = vec![1.0; 64];
+    for j in 0..8 {
+        radii[j * 8 + 3] = 2.0;
+    }
+    assert!(find_thin_features(&radii, 8, 8, TILE, (0, 3), 0.5).is_empty());
+    assert_eq!(find_thin_features(&radii, 8, 8, TILE, (1, 0), 0.5).len(), 1);
+}
@@ -0,0 +1,290 @@
+use crate::image::is_float_image;
+use crate::plane::{Plane, Stacking};
+use image::{DynamicImage, Rgba, Rgba32FImage};
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Blur(f64),
+    Sharpen(f64, f64),
+    Median(usize),
+    Sobel,
+    Canny(f64, f64),
+}
+
+impl Filter {
+    // Distance in pixels that filter takes values from
+    pub fn reach(&self) -> f64 {
+        match *self {
+            Filter::Blur(sigma) | Filter::Sharpen(sigma, _) => 3.0 * sigma,
+            Filter::Median(radius) => radius as f64,
+            Filter::Sobel | Filter::Canny(..) => 1.0,
+        }
+    }
+}
+
+impl Plane {
+    // Kernel is limited to plane size, wider one would only wrap around it again
+    pub fn gaussian_blur(&self, sigma: f64) -> Plane {
+        let radius = (3.0 * sigma).ceil().min(self.width.max(self.height) as f64) as isize;
+        let kernel: Vec<f64> = (-radius..=radius)
+            .map(|k| (-{ (k * k) as f64 } / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let kernel_sum: f64 = kernel.iter().sum();
+        let convolve = |plane: &Plane, di: isize, dj: isize| {
+            plane.map_pixels(|i, j| {
+                (-radius..=radius)
+                    .zip(kernel.iter())
+                    .map(|(k, weight)| weight * plane.get(i + k * di, j + k * dj))
+                    .sum::<f64>()
+                    / kernel_sum
+            })
+        };
+        convolve(&convolve(self, 1, 0), 0, 1)
+    }
+
+    fn unsharp_mask(&self, sigma: f64, amount: f64) -> Plane {
+        let blurred = self.gaussian_blur(sigma);
+        self.map_pixels(|i, j| {
+            let value = self.get(i, j);
+            value + amount * (value - blurred.get(i, j))
+        })
+    }
+
+    // Non-finite samples are skipped, pixel keeps its value if there is nothing else
+    fn median(&self, radius: usize) -> Plane {
+        let radius = radius as isize;
+        self.map_pixels(|i, j| {
+            let mut window: Vec<f64> = (-radius..=radius)
+                .flat_map(|dj| (-radius..=radius).map(move |di| (di, dj)))
+                .map(|(di, dj)| self.get(i + di, j + dj))
+                .filter(|value| value.is_finite())
+                .collect();
+            if window.is_empty() {
+                return self.get(i, j);
+            }
+            let middle = window.len() / 2;
+            window.select_nth_unstable_by(middle, |a, b| a.partial_cmp(b).unwrap());
+            window[middle]
+        })
+    }
+
+    fn sobel_gradients(&self) -> (Plane, Plane) {
+        let gradient_x = self.map_pixels(|i, j| {
+            self.get(i + 1, j - 1) + 2.0 * self.get(i + 1, j) + self.get(i + 1, j + 1)
+                - self.get(i - 1, j - 1)
+                - 2.0 * self.get(i - 1, j)
+                - self.get(i - 1, j + 1)
+        });
+        let gradient_y = self.map_pixels(|i, j| {
+            self.get(i - 1, j + 1) + 2.0 * self.get(i, j + 1) + self.get(i + 1, j + 1)
+                - self.get(i - 1, j - 1)
+                - 2.0 * self.get(i, j - 1)
+                - self.get(i + 1, j - 1)
+        });
+        (gradient_x, gradient_y)
+    }
+
+    fn sobel(&self) -> Plane {
+        let (gradient_x, gradient_y) = self.sobel_gradients();
+        let magnitude =
+            gradient_x.map_pixels(|i, j| gradient_x.get(i, j).hypot(gradient_y.get(i, j)));
+        magnitude.normalized()
+    }
+
+    // Low and high hysteresis thresholds are fractions of maximal gradient magnitude
+    fn canny(&self, low: f64, high: f64) -> Plane {
+        const SMOOTHING_SIGMA: f64 = 1.4;
+        let (gradient_x, gradient_y) = self.gaussian_blur(SMOOTHING_SIGMA).sobel_gradients();
+        let magnitude = gradient_x
+            .map_pixels(|i, j| gradient_x.get(i, j).hypot(gradient_y.get(i, j)))
+            .normalized();
+        let thinned = magnitude.map_pixels(|i, j| {
+            let angle = gradient_y
+                .get(i, j)
+                .atan2(gradient_x.get(i, j))
+                .rem_euclid(PI);
+            let (di, dj) = match (angle / (PI / 4.0)).round() as i32 % 4 {
+                0 => (1, 0),
+                1 => (1, 1),
+                2 => (0, 1),
+                _ => (-1, 1),
+            };
+            let value = magnitude.get(i, j);
+            if value >= magnitude.get(i + di, j + dj) && value >= magnitude.get(i - di, j - dj) {
+                value
+            } else {
+                0.0
+            }
+        });
+        let mut edges =
+            thinned.map_pixels(|i, j| if thinned.get(i, j) >= high { 1.0 } else { 0.0 });
+        let mut stack: Vec<(isize, isize)> = (0..edges.values.len())
+            .filter(|&n| edges.values[n] == 1.0)
+            .map(|n| ((n % edges.width) as isize, (n / edges.width) as isize))
+            .collect();
+        while let Some((i, j)) = stack.pop() {
+            for (di, dj) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let n = match self.index(i + di, j + dj) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                if edges.values[n] == 0.0 && thinned.values[n] >= low {
+                    edges.values[n] = 1.0;
+                    stack.push(((n % self.width) as isize, (n / self.width) as isize));
+                }
+            }
+        }
+        edges
+    }
+
+    fn normalized(mut self) -> Plane {
+        let max_value = self.values.iter().cloned().fold(0.0, f64::max);
+        if max_value > 0.0 {
+            self.values.iter_mut().for_each(|value| *value /= max_value);
+        }
+        self
+    }
+}
+
+pub fn apply_filters(image: DynamicImage, filters: &[Filter], stacking: Stacking) -> DynamicImage {
+    // Float heights may go beyond unit range, integer ones are limited to it
+    let clamped = !is_float_image(&image);
+    let rgba_image = image.into_rgba32f();
+    let (width, height) = (rgba_image.width(), rgba_image.height());
+    let mut planes: Vec<Plane> = (0..4)
+        .map(|channel| Plane {
+            width: width as usize,
+            height: height as usize,
+            stacking,
+            values: rgba_image
+                .pixels()
+                .map(|pixel| pixel[channel] as f64)
+                .collect(),
+        })
+        .collect();
+    for filter in filters {
+        let color_planes = planes.drain(..3).collect::<Vec<Plane>>();
+        let new_color_planes = match *filter {
+            Filter::Blur(sigma) => color_planes
+                .iter()
+                .map(|p| p.gaussian_blur(sigma))
+                .collect(),
+            Filter::Sharpen(sigma, amount) => color_planes
+                .iter()
+                .map(|p| p.unsharp_mask(sigma, amount))
+                .collect(),
+            Filter::Median(radius) => color_planes.iter().map(|p| p.median(radius)).collect(),
+            Filter::Sobel | Filter::Canny(..) => {
+                let luma = color_planes[0].map_pixels(|i, j| {
+                    0.2126 * color_planes[0].get(i, j)
+                        + 0.7152 * color_planes[1].get(i, j)
+                        + 0.0722 * color_planes[2].get(i, j)
+                });
+                let edges = match *filter {
+                    Filter::Canny(low, high) => luma.canny(low, high),
+                    _ => luma.sobel(),
+                };
+                vec![edges.clone(), edges.clone(), edges]
+            }
+        };
+        planes.splice(0..0, new_color_planes);
+    }
+    if clamped {
+        for plane in planes.iter_mut() {
+            plane
+                .values
+                .iter_mut()
+                .for_each(|value| *value = value.clamp(0.0, 1.0));
+        }
+    }
+    let filtered_image = Rgba32FImage::from_fn(width, height, |x, y| {
+        let n = { y * width + x } as usize;
+        Rgba([
+            planes[0].values[n] as f32,
+            planes[1].values[n] as f32,
+            planes[2].values[n] as f32,
+            planes[3].values[n] as f32,
+        ])
+    });
+    DynamicImage::ImageRgba32F(filtered_image)
+}
+
+#[cfg(test)]
+fn test_plane(width: usize, values: Vec<f64>) -> Plane {
+    Plane {
+        width,
+        height: values.len() / width,
+        stacking: Stacking::SURFACE,
+        values,
+    }
+}
+
+#[test]
+fn test_median() {
+    let mut values = vec![0.0; 10];
+    values.extend([1.0; 5]);
+    values[7] = 9.0;
+    let filtered = test_plane(5, values).median(1);
+    // Lone spike is removed, bottom row is replicated beyond image edge
+    assert_eq!(filtered.values[7], 0.0);
+    assert_eq!(filtered.values[12], 1.0);
+    let plane = test_plane(3, vec![f64::NAN, 2.0, 3.0, f64::INFINITY, 1.0, 2.0]);
+    let filtered = plane.median(1);
+    assert!(filtered.values.iter().all(|value| value.is_finite()));
+    assert_eq!(filtered.values[0], 2.0);
+    let plane = test_plane(1, vec![f64::NAN, f64::NAN]);
+    assert!(plane.median(1).values[0].is_nan());
+}
+
+#[test]
+fn test_sharpen() {
+    let plane = test_plane(4, vec![0.0, 0.0, 3.0, 3.0]);
+    let sharpened = plane.unsharp_mask(0.5, 1.0);
+    // Edge contrast is increased beyond original range without clamping
+    assert!(sharpened.values[1] < 0.0);
+    assert!(sharpened.values[2] > 3.0);
+    let flat = test_plane(3, vec![2.5; 6]).unsharp_mask(1.0, 2.0);
+    assert!(flat.values.iter().all(|&value| (value - 2.5).abs() < 1e-12));
+    // Integer images stay in unit range
+    let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(4, 2, |x, _| {
+        image::Luma([if x < 2 { 0 } else { 255 }])
+    }));
+    let filtered =
+        apply_filters(image, &[Filter::Sharpen(0.5, 1.0)], Stacking::SURFACE).into_rgba32f();
+    assert!(filtered
+        .pixels()
+        .all(|pixel| (0.0..=1.0).contains(&pixel[0])));
+    assert_eq!(filtered.get_pixel(2, 0)[0], 1.0);
+}
+
+#[test]
+fn test_sobel() {
+    let plane = test_plane(4, vec![0.0, 0.0, 2.0, 2.0, 0.0, 0.0, 2.0, 2.0]);
+    let edges = plane.sobel();
+    // Step between columns 1 and 2, wrapped step between columns 3 and 0
+    assert_eq!(edges.values[..4], [1.0, 1.0, 1.0, 1.0]);
+    let flat = test_plane(3, vec![0.5; 9]).sobel();
+    assert!(flat.values.iter().all(|&value| value == 0.0));
+    let ramp = test_plane(4, vec![0.0, 1.0, 1.0, 0.0]).sobel();
+    assert_eq!(ramp.values, vec![1.0, 1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn test_canny() {
+    let values = (0..36).map(|n| if n % 6 < 3 { 0.0 } else { 1.0 }).collect();
+    let edges = test_plane(6, values).canny(0.1, 0.3);
+    // Step between columns 2 and 3 is marked by single column of edge pixels
+    assert_eq!(edges.values[3 * 6 + 1], 0.0);
+    assert_eq!(edges.values[3 * 6 + 2], 1.0);
+    assert_eq!(edges.values[3 * 6 + 4], 0.0);
+}
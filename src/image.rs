@@ -1,8 +1,75 @@
-use anyhow::{Context, Result};
-use core::cmp::{max, min};
+use crate::collage::Collage;
+use crate::pattern::PatternImage;
+use crate::svg::SvgImage;
+use crate::text::TextImage;
+use crate::tone::ToneCurve;
+use anyhow::{ensure, Context, Result};
 use image::imageops::FilterType;
 use image::io::Reader;
-use image::DynamicImage;
+use image::{DynamicImage, ImageBuffer};
+use std::fs::read;
+use std::path::Path;
+
+// Raster images have fixed pixels, vector images are rendered at required resolution
+pub enum InputImage {
+    Raster(DynamicImage),
+    Svg(SvgImage),
+    Text(TextImage),
+    Pattern(PatternImage),
+    Collage(Collage),
+}
+
+impl InputImage {
+    // Raw height maps have no header, so their size should be given
+    pub fn from_file(filename: &str, raw_size: Option<(u32, u32)>) -> Result<InputImage> {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("svg") | Some("svgz") => Ok(InputImage::Svg(SvgImage::from_file(filename)?)),
+            Some(format @ ("r16" | "r32")) => {
+                let size = raw_size.with_context(|| {
+                    format!("Raw image size (--raw-size) is required for '{}'", filename)
+                })?;
+                Ok(InputImage::Raster(get_raw_image_from_file(
+                    filename,
+                    size,
+                    format == "r32",
+                )?))
+            }
+            _ => Ok(InputImage::Raster(get_image_from_file(filename)?)),
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            InputImage::Raster(image) => (image.width(), image.height()),
+            InputImage::Svg(svg) => svg.dimensions(),
+            InputImage::Text(text) => text.dimensions(),
+            InputImage::Pattern(pattern) => pattern.dimensions(),
+            InputImage::Collage(collage) => collage.dimensions(),
+        }
+    }
+
+    pub fn render(&self, width: u32, height: u32, pixelated: bool) -> Result<DynamicImage> {
+        Ok(match self {
+            InputImage::Raster(image) => resize_image(image.clone(), width, height, pixelated),
+            InputImage::Svg(svg) => svg.render(width, height)?,
+            InputImage::Text(text) => text.render(width, height),
+            InputImage::Pattern(pattern) => pattern.render(width, height),
+            InputImage::Collage(collage) => collage.render(width, height)?,
+        })
+    }
+
+    // Vector artwork is rendered over transparent background, so fill coverage is used
+    pub fn default_height_source(&self) -> HeightSource {
+        match self {
+            InputImage::Svg(_) | InputImage::Text(_) => HeightSource::Alpha,
+            InputImage::Raster(_) | InputImage::Pattern(_) => HeightSource::Luma,
+            InputImage::Collage(collage) => collage.default_height_source(),
+        }
+    }
+}
 
 pub fn get_image_from_file(filename: &str) -> Result<DynamicImage> {
     let image_reader =
@@ -16,6 +83,40 @@ pub fn get_image_from_file(filename: &str) -> Result<DynamicImage> {
     Ok(image)
 }
 
+// Little-endian 16-bit unsigned or 32-bit float samples, row by row from image top
+pub fn get_raw_image_from_file(
+    filename: &str,
+    (width, height): (u32, u32),
+    float: bool,
+) -> Result<DynamicImage> {
+    let data = read(filename).with_context(|| format!("Failed to read file '{}'", filename))?;
+    let sample_size = if float { 4 } else { 2 };
+    let expected_size = { width as usize } * { height as usize } * sample_size;
+    ensure!(
+        data.len() == expected_size,
+        "Raw file '{}' has {} bytes, {}x{} image needs {}",
+        filename,
+        data.len(),
+        width,
+        height,
+        expected_size
+    );
+    let image = if float {
+        let samples = data
+            .chunks_exact(4)
+            .flat_map(|bytes| [f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]); 3])
+            .collect();
+        DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, samples).unwrap())
+    } else {
+        let samples = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, samples).unwrap())
+    };
+    Ok(image)
+}
+
 pub fn resize_image(
     image: DynamicImage,
     target_width: u32,
@@ -31,44 +132,310 @@ pub fn resize_image(
             FilterType::CatmullRom
         }
     };
-    image.resize_exact(target_width, target_height, filter_type)
+    if !is_float_image(&image) {
+        return image.resize_exact(target_width, target_height, filter_type);
+    }
+    // Resampling clamps float values to unit range, so they are moved into its middle
+    // with room left for filter overshoot and restored afterwards
+    let mut image = image.into_rgba32f();
+    let colors = || image.pixels().flat_map(|pixel| pixel.0[..3].to_vec());
+    let finite = colors().filter(|value| value.is_finite());
+    let low = finite.clone().fold(f32::MAX, f32::min);
+    let high = finite.fold(f32::MIN, f32::max);
+    let low = if low <= high { low } else { 0.0 };
+    let scale = if low < high { 0.5 / (high - low) } else { 1.0 };
+    for pixel in image.pixels_mut() {
+        for value in pixel.0[..3].iter_mut() {
+            let finite = if value.is_finite() { *value } else { low };
+            *value = 0.25 + (finite - low) * scale;
+        }
+    }
+    let mut resized = DynamicImage::ImageRgba32F(image)
+        .resize_exact(target_width, target_height, filter_type)
+        .into_rgba32f();
+    for pixel in resized.pixels_mut() {
+        for value in pixel.0[..3].iter_mut() {
+            *value = low + (*value - 0.25) / scale;
+        }
+    }
+    DynamicImage::ImageRgba32F(resized)
+}
+
+#[derive(Clone, Copy)]
+pub enum TransparentLevel {
+    Surface,
+    Bottom,
+    Depth(f64),
+}
+
+#[derive(Clone, Copy)]
+pub enum HeightSource {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luma,
+    Value,
+    Saturation,
+    Mix(f64, f64, f64),
+}
+
+impl HeightSource {
+    // Channels are normalized (from 0 to 1 for integer images, float images keep raw values)
+    fn height(&self, pixel: [f64; 4]) -> f64 {
+        let [red, green, blue, alpha] = pixel;
+        match *self {
+            HeightSource::Red => red,
+            HeightSource::Green => green,
+            HeightSource::Blue => blue,
+            HeightSource::Alpha => alpha,
+            // Same Rec. 709 weights as image library uses for integer images
+            HeightSource::Luma => 0.2126 * red + 0.7152 * green + 0.0722 * blue,
+            HeightSource::Value => red.max(green).max(blue),
+            HeightSource::Saturation => {
+                let value = red.max(green).max(blue);
+                let chroma = value - red.min(green).min(blue);
+                if value == 0.0 {
+                    0.0
+                } else {
+                    chroma / value
+                }
+            }
+            HeightSource::Mix(weight_r, weight_g, weight_b) => {
+                // Negative weights are compensated by offset so that result fits input range
+                let weights = [weight_r, weight_g, weight_b];
+                let weights_sum: f64 = weights.iter().map(|w| w.abs()).sum();
+                let offset: f64 = weights.iter().map(|w| w.min(0.0).abs()).sum();
+                let mixed = [red, green, blue]
+                    .iter()
+                    .zip(weights.iter())
+                    .map(|(channel, weight)| channel * weight)
+                    .sum::<f64>();
+                (mixed + offset) / weights_sum
+            }
+        }
+    }
+}
+
+pub fn is_float_image(image: &DynamicImage) -> bool {
+    matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+// Float images are read without quantization, integer ones are normalized to 0..1
+pub fn image_to_channels(image: DynamicImage, source: HeightSource) -> (Vec<f64>, Vec<f64>) {
+    let to_f64 = |value: u16| { value as f64 } / { u16::MAX as f64 };
+    if is_float_image(&image) {
+        return image
+            .into_rgba32f()
+            .pixels()
+            .map(|pixel| {
+                let channels = pixel.0.map(|channel| channel as f64);
+                (source.height(channels), channels[3])
+            })
+            .unzip();
+    }
+    match source {
+        HeightSource::Luma => image
+            .into_luma_alpha16()
+            .pixels()
+            .map(|pixel| (to_f64(pixel[0]), to_f64(pixel[1])))
+            .unzip(),
+        _ => image
+            .into_rgba16()
+            .pixels()
+            .map(|pixel| (source.height(pixel.0.map(to_f64)), to_f64(pixel[3])))
+            .unzip(),
+    }
+}
+
+pub struct HeightMapping {
+    pub source: HeightSource,
+    pub inverted: bool,
+    pub input_levels: Option<(f64, f64)>,
+    pub transparent_radius: Option<f64>,
+    pub tone: ToneCurve,
 }
 
 pub fn image_to_vector(
     image: DynamicImage,
-    inverted: bool,
+    mapping: &HeightMapping,
     new_min: f64,
     new_max: f64,
 ) -> Vec<f64> {
-    let gray_image = image.into_luma16();
-    let image_vector = rescale_min_max(gray_image.into_vec(), inverted, new_min, new_max);
-    image_vector
+    let (gray_vector, alpha_vector) = image_to_channels(image, mapping.source);
+    let alpha_mask = mapping.transparent_radius.map(|_| alpha_vector.as_slice());
+    let input_range = mapping
+        .input_levels
+        .or_else(|| find_min_max(&gray_vector, alpha_mask));
+    let transparent_level = mapping
+        .transparent_radius
+        .map(|radius| (radius - new_min) / (new_max - new_min));
+    let mut heights = match (input_range, transparent_level) {
+        (Some(range), _) => {
+            let heights = rescale_min_max(gray_vector, range, mapping.inverted, 0.0, 1.0);
+            if mapping.tone.is_identity() {
+                heights
+            } else {
+                heights.iter().map(|h| mapping.tone.apply(*h)).collect()
+            }
+        }
+        (None, None) => {
+            eprintln!("warning: Image is solid color");
+            vec![0.5f64; gray_vector.len()]
+        }
+        (None, Some(level)) => {
+            eprintln!("warning: Opaque part of image is solid color");
+            // Solid color opaque area is placed at level farthest from transparent one
+            let solid_level = if level > 0.5 { 0.0 } else { 1.0 };
+            vec![solid_level; gray_vector.len()]
+        }
+    };
+    if let Some(level) = transparent_level {
+        for (height, alpha) in heights.iter_mut().zip(alpha_vector.iter()) {
+            let opacity = alpha.clamp(0.0, 1.0);
+            *height = level + (*height - level) * opacity;
+        }
+    }
+    heights
+        .iter()
+        .map(|height| new_min + height * (new_max - new_min))
+        .collect()
+}
+
+// Fully transparent pixels (if alpha is given) and non-finite values are excluded from min/max search
+fn find_min_max(input_vector: &[f64], alpha_vector: Option<&[f64]>) -> Option<(f64, f64)> {
+    let mut global_max = f64::MIN;
+    let mut gloabl_min = f64::MAX;
+    for (n, point) in input_vector.iter().enumerate() {
+        if !point.is_finite() || alpha_vector.map_or(false, |alpha| alpha[n] <= 0.0) {
+            continue;
+        }
+        global_max = point.max(global_max);
+        gloabl_min = point.min(gloabl_min);
+    }
+    if gloabl_min < global_max {
+        Some((gloabl_min, global_max))
+    } else {
+        None
+    }
 }
 
+// Values outside of input range are clipped, non-finite values are placed at its bottom
 pub fn rescale_min_max(
-    input_vector: Vec<u16>,
+    input_vector: Vec<f64>,
+    input_range: (f64, f64),
     inverted: bool,
     new_min: f64,
     new_max: f64,
 ) -> Vec<f64> {
-    let mut global_max: u16 = std::u16::MIN;
-    let mut gloabl_min: u16 = std::u16::MAX;
-    for point in input_vector.iter() {
-        global_max = max(*point, global_max);
-        gloabl_min = min(*point, gloabl_min);
-    }
-    if gloabl_min != global_max {
-        if inverted {
-            (gloabl_min, global_max) = (global_max, gloabl_min);
-        }
-        let scale: f64 = (new_max - new_min) / (global_max as f64 - gloabl_min as f64);
-        let output_vector: Vec<f64> = input_vector
-            .iter()
-            .map(|x| new_min + ({ *x as f64 } - gloabl_min as f64) * scale)
-            .collect();
-        output_vector
-    } else {
-        eprintln!("warning: Image is solid color");
-        vec![0.5f64; input_vector.len()]
+    let (mut input_min, mut input_max) = input_range;
+    if inverted {
+        (input_min, input_max) = (input_max, input_min);
     }
+    let scale: f64 = (new_max - new_min) / (input_max - input_min);
+    let output_vector: Vec<f64> = input_vector
+        .iter()
+        .map(|x| {
+            let value = if x.is_finite() { *x } else { input_range.0 };
+            new_min + (value.clamp(input_range.0, input_range.1) - input_min) * scale
+        })
+        .collect();
+    output_vector
+}
+
+#[cfg(test)]
+#[test]
+fn test_resize_float_image() {
+    let image = DynamicImage::ImageRgb32F(ImageBuffer::from_fn(4, 1, |x, _| {
+        image::Rgb([[-1.0, 0.5, 3.0, f32::NAN][x as usize]; 3])
+    }));
+    let resized = resize_image(image.clone(), 8, 2, true).into_rgba32f();
+    let row: Vec<f32> = (0..8).map(|x| resized.get_pixel(x, 1)[0]).collect();
+    let expected = [-1.0, -1.0, 0.5, 0.5, 3.0, 3.0, -1.0, -1.0];
+    assert!(row.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5));
+    let resized = resize_image(image, 2, 1, false).into_rgba32f();
+    assert!(resized.get_pixel(1, 0)[0] > 1.0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_raw_image() {
+    let filename = std::env::temp_dir().join(format!("img2roller_{}.r32", std::process::id()));
+    let samples: Vec<u8> = [0.25f32, 1.5, -2.0, 0.0]
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    std::fs::write(&filename, samples).unwrap();
+    let filename = filename.to_str().unwrap();
+    assert!(InputImage::from_file(filename, None).is_err());
+    assert!(InputImage::from_file(filename, Some((3, 1))).is_err());
+    let image = get_raw_image_from_file(filename, (2, 2), true).unwrap();
+    let (values, _) = image_to_channels(image, HeightSource::Red);
+    assert_eq!(values, vec![0.25, 1.5, -2.0, 0.0]);
+    let image = get_raw_image_from_file(filename, (8, 1), false).unwrap();
+    assert_eq!(image.into_luma16().get_pixel(1, 0)[0], 0x3E80);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[cfg(test)]
+const TEST_MAPPING: HeightMapping = HeightMapping {
+    source: HeightSource::Luma,
+    inverted: false,
+    input_levels: None,
+    transparent_radius: None,
+    tone: ToneCurve::IDENTITY,
+};
+
+#[test]
+fn test_transparent_areas() {
+    let image = DynamicImage::ImageLumaA16(ImageBuffer::from_fn(4, 1, |x, _| {
+        image::LumaA([{ 1000 * x } as u16, if x == 0 { 0 } else { u16::MAX }])
+    }));
+    // Transparent pixel is excluded from min/max and placed at given radius
+    let mapping = HeightMapping {
+        transparent_radius: Some(1.0),
+        ..TEST_MAPPING
+    };
+    let radii = image_to_vector(image.clone(), &mapping, 0.5, 1.0);
+    assert_eq!(radii, vec![1.0, 0.5, 0.75, 1.0]);
+    // Alpha channel is ignored without transparent level
+    let radii = image_to_vector(image, &TEST_MAPPING, 0.5, 1.0);
+    assert_eq!((radii[0], radii[3]), (0.5, 1.0));
+}
+
+#[test]
+fn test_height_sources() {
+    let (blue, red, white) = ([0.0, 0.0, 1.0, 1.0], [1.0, 0.0, 0.0, 1.0], [1.0; 4]);
+    let heights = |source: HeightSource| [blue, red, white].map(|pixel| source.height(pixel));
+    assert_eq!(heights(HeightSource::Red), [0.0, 1.0, 1.0]);
+    assert_eq!(heights(HeightSource::Value), [1.0, 1.0, 1.0]);
+    assert_eq!(heights(HeightSource::Saturation), [1.0, 1.0, 0.0]);
+    // Negative weight is offset, so that mix stays within channel range
+    assert_eq!(heights(HeightSource::Mix(1.0, 0.0, -1.0)), [0.0, 1.0, 0.5]);
+}
+
+#[test]
+fn test_input_levels() {
+    let image = DynamicImage::ImageLuma16(ImageBuffer::from_fn(3, 1, |x, _| {
+        image::Luma([{ 16384 * (x + 1) } as u16])
+    }));
+    let radii = image_to_vector(image.clone(), &TEST_MAPPING, 0.5, 1.0);
+    assert_eq!(radii, vec![0.5, 0.75, 1.0]);
+    // Fixed levels keep absolute tone instead of stretching it to image min/max
+    let mapping = HeightMapping {
+        input_levels: Some((0.0, 1.0)),
+        ..TEST_MAPPING
+    };
+    let radii = image_to_vector(image.clone(), &mapping, 0.5, 1.0);
+    assert!((radii[0] - 0.625).abs() < 1e-4 && (radii[1] - 0.75).abs() < 1e-4);
+    // Values beyond levels are clipped
+    let mapping = HeightMapping {
+        input_levels: Some((0.3, 0.5)),
+        ..TEST_MAPPING
+    };
+    let radii = image_to_vector(image, &mapping, 0.5, 1.0);
+    assert_eq!(radii, vec![0.5, 1.0, 1.0]);
 }
@@ -0,0 +1,134 @@
+use crate::image::{image_to_vector, is_float_image, HeightMapping, InputImage};
+use crate::pattern::Pattern;
+use crate::tone::ToneCurve;
+use crate::transform::keep_precision;
+use anyhow::Result;
+use image::imageops::replace;
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+#[derive(Clone, Copy)]
+pub enum BlendOperator {
+    Max,
+    Min,
+    Add,
+    Multiply,
+    Mask,
+}
+
+#[derive(Clone)]
+pub enum LayerSource {
+    Image(String),
+    Pattern(Pattern),
+    Text(String),
+}
+
+// Layer heights are scaled by depth scale before blending
+#[derive(Clone)]
+pub struct Layer {
+    pub operator: BlendOperator,
+    pub scale: f64,
+    pub source: LayerSource,
+}
+
+// Layer is fitted into given size keeping its aspect ratio and centered,
+// generated patterns already match image copy and fill it completely
+pub fn render_layer(
+    input: &InputImage,
+    width: u32,
+    height: u32,
+    pixelated: bool,
+) -> Result<DynamicImage> {
+    if let InputImage::Pattern(_) = input {
+        return input.render(width, height, pixelated);
+    }
+    let (input_width, input_height) = input.dimensions();
+    let scale = ({ width as f64 } / { input_width as f64 })
+        .min({ height as f64 } / { input_height as f64 });
+    let fitted_width = ({ input_width as f64 } * scale)
+        .round()
+        .clamp(1.0, width as f64) as u32;
+    let fitted_height = ({ input_height as f64 } * scale)
+        .round()
+        .clamp(1.0, height as f64) as u32;
+    let rendered = input.render(fitted_width, fitted_height, pixelated)?;
+    let float = is_float_image(&rendered);
+    let mut canvas = ImageBuffer::from_pixel(width, height, Rgba([0f32, 0.0, 0.0, 0.0]));
+    let (x, y) = ((width - fitted_width) / 2, (height - fitted_height) / 2);
+    replace(&mut canvas, &rendered.into_rgba32f(), x as i64, y as i64);
+    Ok(keep_precision(DynamicImage::ImageRgba32F(canvas), float))
+}
+
+// Integer layer image values are used as is (0 is full depth, 1 is surface),
+// float ones are either in mm (relief depth is given) or stretched to full range
+// with transparent margins kept at full depth
+pub fn layer_heights(
+    input: &InputImage,
+    image: DynamicImage,
+    relief_depth_mm: Option<f64>,
+) -> Vec<f64> {
+    let float = is_float_image(&image);
+    let input_levels = match (float, relief_depth_mm) {
+        (false, _) => Some((0.0, 1.0)),
+        (true, Some(depth)) => Some((0.0, depth)),
+        (true, None) => None,
+    };
+    let mapping = HeightMapping {
+        source: input.default_height_source(),
+        inverted: false,
+        input_levels,
+        transparent_radius: if float { Some(0.0) } else { None },
+        tone: ToneCurve::IDENTITY,
+    };
+    image_to_vector(image, &mapping, 0.0, 1.0)
+}
+
+// Heights are normalized (0 is full depth, 1 is surface)
+pub fn blend_layer(heights: &mut [f64], layer: &[f64], operator: BlendOperator, scale: f64) {
+    for (height, value) in heights.iter_mut().zip(layer) {
+        let value = scale * value;
+        *height = match operator {
+            BlendOperator::Max => height.max(value),
+            BlendOperator::Min => height.min(value),
+            BlendOperator::Add => *height + value,
+            // Layer value of 1 keeps height, 0 cuts it to full depth (with unit scale)
+            BlendOperator::Multiply => *height * (1.0 - scale + value),
+            // Layer value of 0 removes relief (raises to surface), 1 keeps it
+            BlendOperator::Mask => {
+                let keep = 1.0 - scale + value;
+                1.0 - keep * (1.0 - *height)
+            }
+        }
+        .clamp(0.0, 1.0);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_blend_layer() {
+    let layer = [0.0, 0.5, 1.0];
+    let blend = |operator, scale| {
+        let mut heights = vec![0.5; 3];
+        blend_layer(&mut heights, &layer, operator, scale);
+        heights
+    };
+    assert_eq!(blend(BlendOperator::Max, 1.0), vec![0.5, 0.5, 1.0]);
+    assert_eq!(blend(BlendOperator::Min, 0.5), vec![0.0, 0.25, 0.5]);
+    assert_eq!(blend(BlendOperator::Add, 1.0), vec![0.5, 1.0, 1.0]);
+    assert_eq!(blend(BlendOperator::Multiply, 1.0), vec![0.0, 0.25, 0.5]);
+    assert_eq!(blend(BlendOperator::Multiply, 0.5), vec![0.25, 0.375, 0.5]);
+    assert_eq!(blend(BlendOperator::Mask, 1.0), vec![1.0, 0.75, 0.5]);
+}
+
+#[test]
+fn test_float_layer_heights() {
+    let input = InputImage::Raster(DynamicImage::ImageRgb32F(ImageBuffer::from_fn(
+        3,
+        1,
+        |x, _| image::Rgb([2.0 + x as f32; 3]),
+    )));
+    let image = render_layer(&input, 5, 1, true).unwrap();
+    let heights = layer_heights(&input, image.clone(), None);
+    assert_eq!(heights, vec![0.0, 0.0, 0.5, 1.0, 0.0]);
+    let heights = layer_heights(&input, image, Some(8.0));
+    assert_eq!(heights, vec![0.0, 0.25, 0.375, 0.5, 0.0]);
+}
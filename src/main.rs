@@ -1,22 +1,49 @@
 mod circles;
 mod cli;
+mod collage;
 mod construct;
+mod decimate;
 mod eartrim;
+mod features;
+mod filters;
 mod image;
+mod layers;
+mod normals;
 mod parameters;
+mod pattern;
+mod plane;
+mod seams;
 mod split;
 mod stl;
+mod subdivide;
+mod svg;
+mod terrace;
+mod text;
+mod threshold;
+mod tone;
+mod transform;
+mod validate;
 mod vectors;
+use cli::cli_command;
 use construct::make_pattern_roller;
 use parameters::Parameters;
 use std::process::ExitCode;
 use stl::STLFileWriter;
+use validate::validate_stl_file;
 
 fn actual_work() -> Result<(), anyhow::Error> {
-    let parameters = Parameters::parse_arguments_and_file()?;
+    let matches = cli_command().get_matches();
+    if let Some(validate_matches) = matches.subcommand_matches("validate-stl") {
+        return validate_stl_file(validate_matches.get_one::<String>("filename").unwrap());
+    }
+    let parameters = Parameters::parse_arguments_and_file(matches)?;
     parameters.print_summary()?;
     let stl_writer = STLFileWriter::new(&parameters)?;
-    make_pattern_roller(&parameters, stl_writer)
+    make_pattern_roller(&parameters, stl_writer)?;
+    if parameters.validate {
+        validate_stl_file(&parameters.output_filename)?;
+    }
+    Ok(())
 }
 
 fn main() -> ExitCode {
@@ -0,0 +1,179 @@
+use crate::circles::CircleConverter;
+use crate::parameters::Parameters;
+use crate::plane::{Plane, Stacking};
+use crate::split::split_quad_optimal;
+use crate::validate::count_self_intersections;
+use crate::vectors::Vector3;
+use anyhow::{ensure, Result};
+use std::f64::consts::{PI, TAU};
+
+// Vertices are offset from relief bottom along normals of smoothed relief surface.
+// Offsets are stored in local frame of vertex column: (radial, tangential, axial).
+// Top and bottom rows are offset radially to match lids.
+pub struct NormalDisplacement {
+    columns: usize,
+    offsets: Vec<(f64, f64, f64)>,
+}
+
+impl NormalDisplacement {
+    pub fn new(params: &Parameters, smoothing: f64) -> Result<NormalDisplacement> {
+        let (width, height) = (params.surface_width(), params.surface_height());
+        let plane = Plane {
+            width,
+            height,
+            stacking: Stacking::SURFACE,
+            values: (0..height)
+                .flat_map(|j| (0..width).map(move |i| (i, j)))
+                .map(|(i, j)| params.get_rho(i, j))
+                .collect(),
+        };
+        let smoothed = if smoothing > 0.0 {
+            plane.gaussian_blur(smoothing)
+        } else {
+            plane
+        };
+        let phi_step = TAU / { width as f64 };
+        let z_step = params.roller_length / { (height - 1) as f64 };
+        let bottom_radius = params.bottom_radius();
+        let (columns, rows) = (params.vertex_columns(), params.vertex_rows());
+        let scale = columns / width;
+        let mut offsets = Vec::with_capacity(columns * rows);
+        for j in 0..rows {
+            for i in 0..columns {
+                let rho = params.get_vertex_rho(i, j);
+                let (i_px, j_px) = ((i / scale) as isize, (j / scale) as isize);
+                let normal = if j == 0 || j == rows - 1 {
+                    (1.0, 0.0, 0.0)
+                } else {
+                    let s = smoothed.get(i_px, j_px);
+                    let ds = (smoothed.get(i_px + 1, j_px) - smoothed.get(i_px - 1, j_px))
+                        / (2.0 * s * phi_step);
+                    let dz = (smoothed.get(i_px, j_px - 1) - smoothed.get(i_px, j_px + 1))
+                        / (2.0 * z_step);
+                    let length = (1.0 + ds * ds + dz * dz).sqrt();
+                    (length.recip(), -ds / length, -dz / length)
+                };
+                let depth = rho - bottom_radius;
+                offsets.push((
+                    bottom_radius + depth * normal.0,
+                    depth * normal.1,
+                    depth * normal.2,
+                ));
+            }
+        }
+        let displacement = NormalDisplacement { columns, offsets };
+        let inverted = displacement.count_inverted_faces(params, phi_step, z_step);
+        ensure!(
+            inverted == 0,
+            "Relief along normals reverses order of vertices in {} places, increase normal smoothing or reduce relief depth",
+            inverted
+        );
+        let triangles = displacement.surface_triangles(params, phi_step, z_step);
+        let intersections = count_self_intersections(&triangles);
+        ensure!(
+            intersections == 0,
+            "Relief along normals intersects itself in {} places, increase normal smoothing or reduce relief depth",
+            intersections
+        );
+        Ok(displacement)
+    }
+
+    fn position(&self, circle: &CircleConverter, i: usize, j: usize, z: f64) -> [f64; 3] {
+        let (radial, tangential, axial) = self.offsets[j * self.columns + i % self.columns];
+        let (x, y) = circle.get_xy_local(i, radial, tangential);
+        [x, y, z + axial]
+    }
+
+    pub fn get_vector3(&self, circle: &CircleConverter, i: usize, j: usize, z: f64) -> Vector3 {
+        let [x, y, z] = self.position(circle, i, j, z);
+        Vector3::new(x, y, z)
+    }
+
+    // Displaced surface split into triangles the same way as roller surface, lids are not included
+    fn surface_triangles(
+        &self,
+        params: &Parameters,
+        phi_step: f64,
+        z_step: f64,
+    ) -> Vec<[[f64; 3]; 3]> {
+        let circle = CircleConverter::with_angles(self.columns, 0.0, |n| {
+            params.vertex_position(n) * phi_step
+        });
+        let rows = self.offsets.len() / self.columns;
+        let point = |i: usize, j: usize| {
+            let z = -(params.vertex_position(j) * z_step).clamp(0.0, params.roller_length);
+            self.position(&circle, i, j, z)
+        };
+        let mut triangles = Vec::with_capacity(2 * self.columns * (rows - 1));
+        for j in 0..rows - 1 {
+            for i in 0..self.columns {
+                let (tl, tr) = (point(i, j), point(i + 1, j));
+                let (bl, br) = (point(i, j + 1), point(i + 1, j + 1));
+                if split_quad_optimal(params, i, j).0 {
+                    triangles.extend([[tl, br, tr], [bl, br, tl]]);
+                } else {
+                    triangles.extend([[bl, tr, tl], [bl, br, tr]]);
+                }
+            }
+        }
+        triangles
+    }
+
+    // Displaced grid should keep order of vertices in both directions,
+    // otherwise neighbouring faces are turned inside out
+    fn count_inverted_faces(&self, params: &Parameters, phi_step: f64, z_step: f64) -> usize {
+        let rows = self.offsets.len() / self.columns;
+        let position = |i: usize, j: usize| {
+            let (radial, tangential, axial) = self.offsets[j * self.columns + i % self.columns];
+            let phi = params.vertex_position(i) * phi_step + tangential.atan2(radial);
+            let z = -(params.vertex_position(j) * z_step).clamp(0.0, params.roller_length);
+            (phi, z + axial)
+        };
+        let mut inverted = 0;
+        for j in 0..rows {
+            for i in 0..self.columns {
+                let (phi, z) = position(i, j);
+                let (phi_next, _) = position(i + 1, j);
+                if phi_next <= phi || phi_next - phi >= PI {
+                    inverted += 1;
+                }
+                if j + 1 < rows && position(i, j + 1).1 >= z {
+                    inverted += 1;
+                }
+            }
+        }
+        inverted
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_displacement_follows_normals() {
+    use crate::parameters::test_cli_arguments_with_pixels;
+    use image::Luma;
+    let offsets = |pixel: fn(u32, u32) -> Luma<u16>| {
+        let arguments = "img2roller -d 20 -e 1 --normal-smoothing 0 --relief-along-normals t.png";
+        let parameters = test_cli_arguments_with_pixels(arguments, (8, 8), pixel).unwrap();
+        let displacement = parameters.normal_displacement.unwrap();
+        let columns = displacement.columns;
+        (displacement.offsets, columns)
+    };
+    // Flat relief is offset radially only
+    let (flat, _) = offsets(|_, _| Luma([0]));
+    assert!(flat
+        .iter()
+        .all(|&(_, tangential, axial)| tangential == 0.0 && axial == 0.0));
+    // Relief rising around circumference is tilted backwards, radial part gets shorter
+    let (ramp, columns) = offsets(|x, _| Luma([{ 8000 * x.min(7 - x) } as u16]));
+    let (radial, tangential, axial) = ramp[3 * columns + 1];
+    let depth = (radial - 9.0).hypot(tangential);
+    assert!(tangential < 0.0 && axial.abs() < 1e-12);
+    assert!(radial - 9.0 < depth);
+    assert!(ramp[3 * columns + 6].1 > 0.0);
+    // Relief rising along axis (towards bottom) is tilted upwards
+    let (ramp, columns) = offsets(|_, y| Luma([{ 8000 * y } as u16]));
+    let (_, tangential, axial) = ramp[3 * columns + 1];
+    assert!(tangential.abs() < 1e-12 && axial > 0.0);
+    // Top and bottom rows match lids
+    assert_eq!(ramp[1].2, 0.0);
+}
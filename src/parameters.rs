@@ -1,26 +1,115 @@
+#[cfg(test)]
 use crate::cli::cli_command;
-use crate::image::{get_image_from_file, image_to_vector, resize_image};
+use crate::collage::{Collage, Layout};
+use crate::decimate::AdaptiveMesh;
+use crate::features::{
+    element_radius, find_thin_features, remove_thin_features, report_thin_features, FeatureAction,
+};
+use crate::filters::{apply_filters, Filter};
+use crate::image::{
+    image_to_channels, image_to_vector, is_float_image, resize_image, HeightMapping, HeightSource,
+    InputImage, TransparentLevel,
+};
+use crate::layers::{blend_layer, layer_heights, render_layer, Layer, LayerSource};
+use crate::normals::NormalDisplacement;
+use crate::pattern::{Pattern, PatternImage, Profile};
+use crate::plane::Stacking;
+use crate::seams::{blend_seams, check_seams, SeamBlend};
+use crate::split::SplitStrategy;
+use crate::subdivide::Subdivision;
+use crate::terrace::{Terraces, Terracing};
+use crate::text::TextImage;
+use crate::threshold::{apply_threshold, Threshold};
+use crate::tone::{read_lut_file, ToneCurve};
+use crate::transform::{orient_image, pad_image, Crop, Flip, Padding};
 use anyhow::{ensure, Context, Result};
 use clap::ArgMatches;
 use image::DynamicImage;
 #[cfg(test)]
-use image::Rgb32FImage;
+use image::{ImageBuffer, Luma, LumaA, Rgb, Rgb32FImage};
 use std::f64::consts::{PI, TAU};
+use std::path::Path;
 
 pub struct Parameters {
     pub output_filename: String,
+    pub validate: bool,
     pub radii_vector: Vec<f64>,
     pub image_width: u32,
     pub image_height: u32,
     pub stack_horizontal: u32,
     pub stack_vertical: u32,
+    pub stack_horizontal_rule: StackRule,
+    pub stack_vertical_rule: StackRule,
     pub roller_diameter: f64,
     pub roller_length: f64,
     pub relief_depth: f64,
-    pub grid_step: f64,
+    pub relief_direction: ReliefDirection,
+    pub circumferential_step: f64,
+    pub axial_step: f64,
+    // Different steps were requested, derived ones differ only by rounding otherwise
+    pub anisotropic_grid: bool,
+    pub mirrored_for_imprint: bool,
+    pub crisp_walls: bool,
+    pub terraces: Option<Terraces>,
+    pub normal_displacement: Option<NormalDisplacement>,
+    pub split_strategy: SplitStrategy,
+    pub subdivision: Option<Subdivision>,
+    pub adaptive_mesh: Option<AdaptiveMesh>,
     pub roller_end: RollerEnd,
 }
 
+#[derive(Clone, Copy)]
+pub enum StackRule {
+    Repeat,
+    Mirror,
+    Rotate,
+}
+
+impl StackRule {
+    fn from_name(name: Option<&String>) -> StackRule {
+        match name.map(String::as_str) {
+            Some("mirror") => StackRule::Mirror,
+            Some("rotate") => StackRule::Rotate,
+            _ => StackRule::Repeat,
+        }
+    }
+
+    // Flips of image copy as (along stacking direction, across stacking direction)
+    pub fn flips(&self, copy_index: usize) -> (bool, bool) {
+        match (self, copy_index % 2) {
+            (_, 0) | (StackRule::Repeat, _) => (false, false),
+            (StackRule::Mirror, _) => (true, false),
+            (StackRule::Rotate, _) => (true, true),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ReliefDirection {
+    Inward,
+    Outward,
+    Centered,
+}
+
+impl ReliefDirection {
+    fn from_name(name: Option<&String>) -> ReliefDirection {
+        match name.map(String::as_str) {
+            Some("outward") => ReliefDirection::Outward,
+            Some("centered") => ReliefDirection::Centered,
+            _ => ReliefDirection::Inward,
+        }
+    }
+
+    // Radii of relief bottom and surface for given roller radius
+    fn radius_range(&self, radius: f64, relief_depth: f64) -> (f64, f64) {
+        match self {
+            ReliefDirection::Inward => (radius - relief_depth, radius),
+            ReliefDirection::Outward => (radius, radius + relief_depth),
+            ReliefDirection::Centered => (radius - relief_depth * 0.5, radius + relief_depth * 0.5),
+        }
+    }
+}
+
 pub enum RollerEnd {
     Flat,
     Pin {
@@ -35,28 +124,59 @@ pub enum RollerEnd {
 }
 
 impl Parameters {
-    pub fn parse_arguments_and_file() -> Result<Parameters> {
-        let matches = cli_command().get_matches();
-        let input_filename = matches.get_one::<String>("filename").unwrap();
-        let raw_image = get_image_from_file(input_filename)?;
-        parse_macthes(matches, raw_image)
+    // Offset (in pixels) of vertices from pixel center, each pixel of crisp walls surface
+    // is represented by 2x2 vertices, so that walls between pixels are almost vertical
+    const CRISP_WALL_VERTEX_OFFSET: f64 = 0.45;
+
+    pub fn parse_arguments_and_file(matches: ArgMatches) -> Result<Parameters> {
+        let raw_size = matches.get_one::<(u32, u32)>("raw_size").copied();
+        let input_image = if let Some(text) = matches.get_one::<String>("text") {
+            let font_filename = matches.get_one::<String>("font").unwrap();
+            InputImage::Text(TextImage::new(text, font_filename)?)
+        } else if let Some(&pattern) = matches.get_one::<Pattern>("pattern") {
+            InputImage::Pattern(pattern_image(&matches, pattern)?)
+        } else if let Some(bands) = matches.get_many::<(String, u32)>("band") {
+            let pixelated = matches.get_flag("pixelated");
+            InputImage::Collage(Collage::from_files(
+                Layout::Bands,
+                bands,
+                raw_size,
+                pixelated,
+            )?)
+        } else if let Some(segments) = matches.get_many::<(String, u32)>("segment") {
+            let pixelated = matches.get_flag("pixelated");
+            InputImage::Collage(Collage::from_files(
+                Layout::Segments,
+                segments,
+                raw_size,
+                pixelated,
+            )?)
+        } else {
+            let filename = matches.get_one::<String>("filename").unwrap();
+            InputImage::from_file(filename, raw_size)?
+        };
+        parse_macthes(matches, input_image)
     }
 
     pub fn circle_points(&self) -> u32 {
-        self.image_width * self.stack_horizontal
+        self.vertex_columns() as u32
     }
 
     pub fn faces_count(&self) -> Result<u32> {
         const OVERFLOW_ERROR_TEXT: &str =
             "Overflow in STL face counter: resulting model is too big";
-        let full_body_width_points = self.image_width * self.stack_horizontal;
-        let full_body_height_points = self.image_height * self.stack_vertical - 1;
-        let full_body_points = full_body_width_points
-            .checked_mul(full_body_height_points)
-            .with_context(|| OVERFLOW_ERROR_TEXT)?;
-        let full_body_faces = 2u32
-            .checked_mul(full_body_points)
-            .with_context(|| OVERFLOW_ERROR_TEXT)?;
+        let full_body_width_points = self.vertex_columns() as u32;
+        let full_body_height_points = self.vertex_rows() as u32 - 1;
+        let full_body_faces = match &self.adaptive_mesh {
+            Some(mesh) => u32::try_from(mesh.triangles.len()).context(OVERFLOW_ERROR_TEXT)?,
+            None => {
+                let full_body_points = full_body_width_points
+                    .checked_mul(full_body_height_points)
+                    .with_context(|| OVERFLOW_ERROR_TEXT)?;
+                2u32.checked_mul(full_body_points)
+                    .with_context(|| OVERFLOW_ERROR_TEXT)?
+            }
+        };
         let ends_faces_count = match self.roller_end {
             RollerEnd::Flat => 2 * full_body_width_points,
             RollerEnd::Pin { circle_points, .. } => 2 * full_body_width_points + 8 * circle_points,
@@ -70,23 +190,108 @@ impl Parameters {
         Ok(n_faces)
     }
 
+    pub fn bottom_radius(&self) -> f64 {
+        self.relief_direction
+            .radius_range(self.roller_diameter * 0.5, self.relief_depth)
+            .0
+    }
+
+    pub fn surface_radius(&self) -> f64 {
+        self.relief_direction
+            .radius_range(self.roller_diameter * 0.5, self.relief_depth)
+            .1
+    }
+
+    pub fn surface_width(&self) -> usize {
+        (self.image_width * self.stack_horizontal) as usize
+    }
+
+    pub fn surface_height(&self) -> usize {
+        (self.image_height * self.stack_vertical) as usize
+    }
+
     pub fn get_rho(&self, i: usize, j: usize) -> f64 {
-        let index = j * { self.image_width as usize } + i;
-        self.radii_vector[index]
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let (flip_columns_h, flip_rows_h) = self.stack_horizontal_rule.flips(i / width);
+        let (flip_rows_v, flip_columns_v) = self.stack_vertical_rule.flips(j / height);
+        let (mut i, mut j) = (i % width, j % height);
+        if flip_columns_h != flip_columns_v {
+            i = width - 1 - i;
+        }
+        if flip_rows_h != flip_rows_v {
+            j = height - 1 - j;
+        }
+        self.radii_vector[j * width + i]
     }
 
     pub fn get_rho_looped(&self, i_raw: i32, j_raw: i32) -> f64 {
-        let i = i_raw.rem_euclid(self.image_width as i32) as usize;
-        let j = j_raw.rem_euclid(self.image_height as i32) as usize;
+        let i = i_raw.rem_euclid(self.surface_width() as i32) as usize;
+        let j = j_raw.rem_euclid(self.surface_height() as i32) as usize;
         self.get_rho(i, j)
     }
 
-    pub fn get_image_topline(&self) -> &[f64] {
-        &self.radii_vector[..{ self.image_width as usize }]
+    fn vertex_scale(&self) -> usize {
+        if self.crisp_walls {
+            2
+        } else {
+            self.subdivision
+                .as_ref()
+                .map_or(1, |subdivision| subdivision.factor)
+        }
+    }
+
+    pub fn vertex_columns(&self) -> usize {
+        self.surface_width() * self.vertex_scale()
+    }
+
+    // Subdivided rows span from first to last pixel center
+    pub fn vertex_rows(&self) -> usize {
+        match &self.subdivision {
+            Some(subdivision) => subdivision.rows(),
+            None => self.surface_height() * self.vertex_scale(),
+        }
+    }
+
+    pub fn get_vertex_rho(&self, i: usize, j: usize) -> f64 {
+        match &self.subdivision {
+            Some(subdivision) => subdivision.get_rho(i, j),
+            None => self.get_rho(i / self.vertex_scale(), j / self.vertex_scale()),
+        }
+    }
+
+    pub fn get_vertex_rho_looped(&self, i_raw: i32, j_raw: i32) -> f64 {
+        let i = i_raw.rem_euclid(self.vertex_columns() as i32) as usize;
+        let j = j_raw.rem_euclid(self.vertex_rows() as i32) as usize;
+        self.get_vertex_rho(i, j)
+    }
+
+    // Position of vertex row or column measured in pixels from first pixel center
+    pub fn vertex_position(&self, n: usize) -> f64 {
+        if self.crisp_walls {
+            let offset = if n % 2 == 0 {
+                -Parameters::CRISP_WALL_VERTEX_OFFSET
+            } else {
+                Parameters::CRISP_WALL_VERTEX_OFFSET
+            };
+            offset + { (n / 2) as f64 }
+        } else {
+            n as f64 / { self.vertex_scale() as f64 }
+        }
+    }
+
+    pub fn get_surface_line(&self, j: usize) -> Vec<f64> {
+        (0..self.vertex_columns())
+            .map(|i| self.get_vertex_rho(i, j))
+            .collect()
+    }
+
+    pub fn get_surface_topline(&self) -> Vec<f64> {
+        self.get_surface_line(0)
     }
 
-    pub fn get_image_botline(&self) -> &[f64] {
-        &self.radii_vector[self.radii_vector.len() - { self.image_width as usize }..]
+    pub fn get_surface_botline(&self) -> Vec<f64> {
+        self.get_surface_line(self.vertex_rows() - 1)
     }
 }
 
@@ -118,93 +323,498 @@ impl Parameters {
         let size_string = Parameters::format_bytes_size(self.bytes_estimate()?);
         println!(
             "length: {:.2} diameter: {:.2} filesize: {}",
-            self.roller_length, self.roller_diameter, size_string
+            self.roller_length,
+            self.surface_radius() * 2.0,
+            size_string
         );
+        if self.anisotropic_grid {
+            println!(
+                "grid step: {:.3} around circumference, {:.3} along axis",
+                self.circumferential_step, self.axial_step
+            );
+        }
+        if let Some(terraces) = &self.terraces {
+            println!(
+                "terraces: {} levels with step {:.3} (depth {:.3})",
+                terraces.levels,
+                terraces.step,
+                terraces.step * { (terraces.levels - 1) as f64 }
+            );
+        }
+        if self.mirrored_for_imprint {
+            println!("note: pattern on roller surface is mirrored, imprint will read correctly");
+        }
         Ok(())
     }
 }
 
-fn parse_macthes(matches: ArgMatches, raw_image: DynamicImage) -> Result<Parameters> {
+// Generated pattern covers one image copy of roller with both dimensions given
+fn pattern_image(matches: &ArgMatches, pattern: Pattern) -> Result<PatternImage> {
+    let (diameter, length) = match (
+        matches.get_one::<f64>("roller_diameter"),
+        matches.get_one::<f64>("roller_length"),
+    ) {
+        (Some(&diameter), Some(&length)) => (diameter, length),
+        _ => anyhow::bail!("Both roller diameter and length are required for pattern"),
+    };
+    ensure!(
+        diameter > 0.0 && length > 0.0,
+        "All roller dimensions should be greater than zero"
+    );
     let stack_horizontal = *matches.get_one::<u32>("stack_horizontal").unwrap_or(&1u32);
     let stack_vertical = *matches.get_one::<u32>("stack_vertical").unwrap_or(&1u32);
-    let surface_width_px = raw_image.width() * stack_horizontal;
-    let surface_height_px = raw_image.height() * stack_vertical;
+    let grid_step = *matches
+        .get_one::<(f64, f64)>("grid_step")
+        .unwrap_or(&(pattern.default_grid_step(), pattern.default_grid_step()));
+    // Each pixel of roller surface makes two faces, which are counted in 32 bits
+    let pixels = (PI * diameter / grid_step.0).round() * (length / grid_step.1).round();
+    ensure!(
+        2.0 * pixels <= u32::MAX as f64,
+        "Grid step is too small for roller dimensions, pattern image would be too big"
+    );
+    Ok(PatternImage {
+        pattern,
+        profile: Profile::from_name(matches.get_one("pattern_profile")),
+        seed: *matches.get_one::<u64>("seed").unwrap_or(&0),
+        circumference: PI * diameter / { stack_horizontal as f64 },
+        length: length / { stack_vertical as f64 },
+        grid_step,
+    })
+}
+
+// Named after input image, pattern or first collage part, so that runs don't overwrite each other
+fn default_output_filename(matches: &ArgMatches) -> String {
+    let collage_part = (matches.get_many::<(String, u32)>("band"))
+        .or_else(|| matches.get_many::<(String, u32)>("segment"))
+        .and_then(|mut parts| parts.next());
+    if let Some(filename) = matches.get_one::<String>("filename") {
+        format!("{}.stl", filename)
+    } else if let Some(pattern) = matches.get_one::<Pattern>("pattern") {
+        format!("{}.stl", pattern.name())
+    } else if let Some((filename, _)) = collage_part {
+        Path::new(filename)
+            .with_extension("stl")
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        String::from("text.stl")
+    }
+}
+
+fn parse_macthes(matches: ArgMatches, input_image: InputImage) -> Result<Parameters> {
+    let stack_horizontal = *matches.get_one::<u32>("stack_horizontal").unwrap_or(&1u32);
+    let stack_vertical = *matches.get_one::<u32>("stack_vertical").unwrap_or(&1u32);
+    let stack_horizontal_rule = StackRule::from_name(matches.get_one("stack_horizontal_rule"));
+    let stack_vertical_rule = StackRule::from_name(matches.get_one("stack_vertical_rule"));
+    let stacking = Stacking {
+        rules: (stack_horizontal_rule, stack_vertical_rule),
+        copies: (stack_horizontal as usize, stack_vertical as usize),
+    };
+    let crop = matches.get_one::<Crop>("crop").copied();
+    let rotation = matches.get_one::<f64>("rotate").copied();
+    let flip = Flip::from_name(matches.get_one("flip"));
+    let input_image = match input_image {
+        InputImage::Raster(image) => InputImage::Raster(orient_image(image, crop, rotation, flip)?),
+        generated => {
+            ensure!(
+                crop.is_none() && rotation.is_none() && flip.is_none(),
+                "Crop, rotation and flip apply to raster images only"
+            );
+            generated
+        }
+    };
+    let generated_pattern = matches!(input_image, InputImage::Pattern(_));
+    // Font, profile and seed should be used by main image or at least one layer
+    let layer_sources: Vec<&LayerSource> = matches
+        .get_many::<Layer>("layer")
+        .map(|layers| layers.map(|layer| &layer.source).collect())
+        .unwrap_or_default();
+    let text_used = matches!(input_image, InputImage::Text(_))
+        || (layer_sources.iter()).any(|source| matches!(source, LayerSource::Text(_)));
+    let pattern_used = generated_pattern
+        || (layer_sources.iter()).any(|source| matches!(source, LayerSource::Pattern(_)));
+    ensure!(
+        text_used || !matches.contains_id("font"),
+        "Font is used only with text or text layers"
+    );
+    ensure!(
+        pattern_used || !(matches.contains_id("pattern_profile") || matches.contains_id("seed")),
+        "Pattern profile and seed are used only with pattern or pattern layers"
+    );
+    let both_dimensions =
+        matches.contains_id("roller_diameter") && matches.contains_id("roller_length");
+    ensure!(
+        !both_dimensions || generated_pattern,
+        "Either diameter or length should be given for input image, not both"
+    );
+    // Margins are included in given roller dimension, so image copy is scaled down to fit
+    let (content_width, content_height) = input_image.dimensions();
+    let padding_pixels = match matches.get_one::<Padding>("pad") {
+        None => [0; 4],
+        Some(padding) => {
+            ensure!(
+                !generated_pattern,
+                "Padding is not supported for generated patterns"
+            );
+            let (copy_size, margins, content_size) =
+                if let Some(&diameter) = matches.get_one::<f64>("roller_diameter") {
+                    let circumference = PI * diameter / { stack_horizontal as f64 };
+                    (circumference, padding.horizontal(), content_width)
+                } else {
+                    let length = *matches.get_one::<f64>("roller_length").unwrap();
+                    (
+                        length / { stack_vertical as f64 },
+                        padding.vertical(),
+                        content_height,
+                    )
+                };
+            ensure!(
+                margins < copy_size,
+                "Margins ({}) should be smaller than image copy size along given dimension ({:.2})",
+                margins,
+                copy_size
+            );
+            padding.to_pixels((copy_size - margins) / { content_size as f64 })
+        }
+    };
+    let [pad_top, pad_right, pad_bottom, pad_left] = padding_pixels;
+    let raw_width = content_width + pad_left + pad_right;
+    let raw_height = content_height + pad_top + pad_bottom;
+    let surface_width_px = raw_width * stack_horizontal;
+    let surface_height_px = raw_height * stack_vertical;
     let surface_aspect_ratio = { surface_width_px as f64 } / { surface_height_px as f64 };
-    let (diameter, length, pixel_size) = if matches.contains_id("roller_diameter") {
+    let (diameter, length) = if both_dimensions {
+        let diameter = *matches.get_one::<f64>("roller_diameter").unwrap();
+        let length = *matches.get_one::<f64>("roller_length").unwrap();
+        (diameter, length)
+    } else if matches.contains_id("roller_diameter") {
         let diameter = *matches.get_one::<f64>("roller_diameter").unwrap();
-        let pixel_size = PI * diameter / { surface_width_px as f64 };
         let length = PI * diameter / surface_aspect_ratio;
-        (diameter, length, pixel_size)
+        (diameter, length)
     } else {
         let length = *matches.get_one::<f64>("roller_length").unwrap();
-        let pixel_size = length / { surface_height_px as f64 };
         let diameter = length * surface_aspect_ratio / PI;
-        (diameter, length, pixel_size)
+        (diameter, length)
     };
+    // Pixels are square unless both dimensions are given (generated pattern)
+    let pixel_size = (
+        PI * diameter / { surface_width_px as f64 },
+        length / { surface_height_px as f64 },
+    );
     ensure!(
         diameter > 0.0 && length > 0.0,
         "All roller dimensions should be greater than zero"
     );
+    // Float values in mm keep their scale, so highest one is default relief depth
+    let heights_in_mm = matches.get_flag("heights_in_mm");
+    let highest_value = match &input_image {
+        InputImage::Raster(image) if heights_in_mm && is_float_image(image) => {
+            let source = *matches
+                .get_one::<HeightSource>("height_source")
+                .unwrap_or(&HeightSource::Luma);
+            let (values, _) = image_to_channels(image.clone(), source);
+            Some(
+                values
+                    .into_iter()
+                    .filter(|v| v.is_finite())
+                    .fold(0.0, f64::max),
+            )
+        }
+        _ => {
+            ensure!(
+                !heights_in_mm,
+                "Heights in mm require float image (OpenEXR, Radiance HDR or raw .r32)"
+            );
+            None
+        }
+    };
     let relief_depth = *matches
         .get_one::<f64>("relief_depth")
-        .unwrap_or(&(0.02 * &diameter));
+        .unwrap_or(&highest_value.unwrap_or(0.02 * diameter));
     ensure!(
         relief_depth > 0.0,
         "Relief depth should be greater than zero"
     );
+    let relief_direction = ReliefDirection::from_name(matches.get_one("relief_direction"));
+    let (bottom_radius, surface_radius) =
+        relief_direction.radius_range(diameter * 0.5, relief_depth);
     ensure!(
-        diameter > 2.0 * relief_depth,
-        "Relief depth ({}) should be less than radius ({})",
-        relief_depth,
-        diameter * 0.5
+        bottom_radius > 0.0,
+        "Relief depth ({}) is too big, relief bottom would reach roller axis",
+        relief_depth
     );
-    let (image, image_width, image_height, grid_step) = if matches.contains_id("grid_step") {
-        let grid_step = *matches.get_one::<f64>("grid_step").unwrap();
-        let scale = pixel_size / grid_step;
-        let target_width = (scale * { raw_image.width() as f64 }).round() as u32;
-        let target_height = (scale * { raw_image.height() as f64 }).round() as u32;
-        let pixelated = matches.get_flag("pixelated");
-        let resized_image = resize_image(raw_image, target_width, target_height, pixelated);
-        (resized_image, target_width, target_height, grid_step)
+    let filters: Vec<Filter> = matches
+        .get_many::<Filter>("filter")
+        .map(|filters| filters.copied().collect())
+        .unwrap_or_default();
+    let filtered = |image: DynamicImage| -> Result<DynamicImage> {
+        if filters.is_empty() {
+            return Ok(image);
+        }
+        let size = image.width().max(image.height());
+        ensure!(
+            filters.iter().all(|filter| filter.reach() <= size as f64),
+            "Filter reaches further than image size ({}x{})",
+            image.width(),
+            image.height()
+        );
+        Ok(apply_filters(image, &filters, stacking))
+    };
+    let default_height_source = input_image.default_height_source();
+    // Image is resized independently along circumference and axis
+    let (image_width, image_height, grid_step) = if matches.contains_id("grid_step") {
+        let grid_step = *matches.get_one::<(f64, f64)>("grid_step").unwrap();
+        let target_width = (pixel_size.0 / grid_step.0 * { raw_width as f64 }).round() as u32;
+        let target_height = (pixel_size.1 / grid_step.1 * { raw_height as f64 }).round() as u32;
+        ensure!(
+            target_width.saturating_mul(stack_horizontal) >= 3 && target_height > 1,
+            "Grid step is too big for roller dimensions"
+        );
+        (target_width, target_height, grid_step)
+    } else {
+        (raw_width, raw_height, pixel_size)
+    };
+    let pixelated = matches.get_flag("pixelated");
+    let image = match input_image {
+        InputImage::Raster(raw_image) if matches.contains_id("grid_step") => {
+            let padded = pad_image(filtered(raw_image)?, padding_pixels);
+            resize_image(padded, image_width, image_height, pixelated)
+        }
+        InputImage::Raster(raw_image) => pad_image(filtered(raw_image)?, padding_pixels),
+        generated => {
+            // Margins are scaled to rendered resolution, rest of it is taken by image
+            let scale_x = { image_width as f64 } / { raw_width as f64 };
+            let scale_y = { image_height as f64 } / { raw_height as f64 };
+            let [top, right, bottom, left] = [
+                pad_top as f64 * scale_y,
+                pad_right as f64 * scale_x,
+                pad_bottom as f64 * scale_y,
+                pad_left as f64 * scale_x,
+            ]
+            .map(|margin| margin.round() as u32);
+            // Resolution of generated image follows grid step, which may exceed circumference
+            ensure!(
+                image_width.saturating_mul(stack_horizontal) >= 3 && image_height > 1,
+                "Image of {}x{} pixels is too small, at least 3 columns around circumference and 2 rows are required, use smaller grid step",
+                image_width,
+                image_height
+            );
+            ensure!(
+                left + right < image_width && top + bottom < image_height,
+                "Margins leave no space for image"
+            );
+            let rendered = generated.render(
+                image_width - left - right,
+                image_height - top - bottom,
+                pixelated,
+            )?;
+            pad_image(filtered(rendered)?, [top, right, bottom, left])
+        }
+    };
+    // Image columns advance counterclockwise when viewed from z_max with image top at z_max,
+    // so surface seen from outside reads as the original image and its imprint is mirrored.
+    let mirrored_for_imprint = matches.get_flag("mirror_for_imprint");
+    let image = if mirrored_for_imprint {
+        image.fliph()
     } else {
-        let width = raw_image.width();
-        let height = raw_image.height();
-        let grid_step = length / { surface_height_px as f64 };
-        (raw_image, width, height, grid_step)
+        image
     };
     let inverted = matches.get_flag("inverted");
-    let radii_vector = image_to_vector(
-        image,
+    // Margins and corners of rotated image are filled with transparent pixels
+    let filled = padding_pixels.iter().any(|margin| *margin > 0)
+        || rotation.map_or(false, |angle| angle.rem_euclid(90.0) != 0.0);
+    let transparent_radius = match matches.get_one::<TransparentLevel>("transparent_level") {
+        None if filled => Some(surface_radius),
+        None => None,
+        Some(TransparentLevel::Surface) => Some(surface_radius),
+        Some(TransparentLevel::Bottom) => Some(bottom_radius),
+        Some(TransparentLevel::Depth(depth)) => {
+            ensure!(
+                *depth >= 0.0 && *depth <= relief_depth,
+                "Depth of transparent areas ({}) should be between zero and relief depth ({})",
+                depth,
+                relief_depth
+            );
+            Some(surface_radius - depth)
+        }
+    };
+    let input_levels = if heights_in_mm {
+        Some((0.0, relief_depth))
+    } else if matches.get_flag("absolute_levels") {
+        Some((0.0, 1.0))
+    } else {
+        matches.get_one::<(f64, f64)>("input_levels").copied()
+    };
+    let curve_points = match matches.get_one::<String>("tone_lut") {
+        Some(lut_filename) => read_lut_file(lut_filename)?,
+        None => matches
+            .get_one::<Vec<(f64, f64)>>("tone_curve")
+            .cloned()
+            .unwrap_or_default(),
+    };
+    let tone = ToneCurve {
+        curve_points,
+        gamma: *matches.get_one::<f64>("gamma").unwrap_or(&1.0),
+        contrast: *matches.get_one::<f64>("contrast").unwrap_or(&1.0),
+        output_levels: *matches
+            .get_one::<(f64, f64)>("output_levels")
+            .unwrap_or(&(0.0, 1.0)),
+    };
+    let height_mapping = HeightMapping {
+        source: *matches
+            .get_one::<HeightSource>("height_source")
+            .unwrap_or(&default_height_source),
         inverted,
-        diameter * 0.5 - relief_depth,
-        diameter * 0.5,
-    );
+        input_levels,
+        transparent_radius,
+        tone,
+    };
+    let mut radii_vector = image_to_vector(image, &height_mapping, bottom_radius, surface_radius);
+    if let Some(layers) = matches.get_many::<Layer>("layer") {
+        let mut heights: Vec<f64> = radii_vector
+            .iter()
+            .map(|rho| (rho - bottom_radius) / relief_depth)
+            .collect();
+        for layer in layers {
+            let layer_input = match &layer.source {
+                LayerSource::Image(filename) => {
+                    let raw_size = matches.get_one::<(u32, u32)>("raw_size").copied();
+                    InputImage::from_file(filename, raw_size)?
+                }
+                LayerSource::Pattern(pattern) => InputImage::Pattern(PatternImage {
+                    pattern: *pattern,
+                    profile: Profile::from_name(matches.get_one("pattern_profile")),
+                    seed: *matches.get_one::<u64>("seed").unwrap_or(&0),
+                    circumference: PI * diameter / { stack_horizontal as f64 },
+                    length: length / { stack_vertical as f64 },
+                    grid_step,
+                }),
+                LayerSource::Text(text) => {
+                    let font_filename = matches
+                        .get_one::<String>("font")
+                        .context("Font file is required for text layers")?;
+                    InputImage::Text(TextImage::new(text, font_filename)?)
+                }
+            };
+            let layer_image = render_layer(&layer_input, image_width, image_height, pixelated)?;
+            let layer_image = if mirrored_for_imprint {
+                layer_image.fliph()
+            } else {
+                layer_image
+            };
+            let relief_depth_mm = if heights_in_mm {
+                Some(relief_depth)
+            } else {
+                None
+            };
+            let values = layer_heights(&layer_input, layer_image, relief_depth_mm);
+            blend_layer(&mut heights, &values, layer.operator, layer.scale);
+        }
+        radii_vector = heights
+            .iter()
+            .map(|height| bottom_radius + height * relief_depth)
+            .collect();
+    }
+    if let Some(&band) = matches.get_one::<u32>("seam_blend") {
+        blend_seams(
+            &mut radii_vector,
+            image_width as usize,
+            image_height as usize,
+            band as usize,
+            SeamBlend::from_name(matches.get_one("seam_blend_mode")),
+            (stack_horizontal_rule, stack_vertical_rule),
+            (stack_horizontal as usize, stack_vertical as usize),
+        );
+    }
+    // Threshold applies to combined and blended map, so that layers and seams get crisp walls too
+    if let Some(&threshold) = matches.get_one::<Threshold>("threshold") {
+        if let Threshold::Adaptive(radius) = threshold {
+            ensure!(
+                radius <= image_width.max(image_height) as usize,
+                "Adaptive threshold radius should not exceed image size ({}x{})",
+                image_width,
+                image_height
+            );
+        }
+        let mut heights: Vec<f64> = radii_vector
+            .iter()
+            .map(|rho| (rho - bottom_radius) / relief_depth)
+            .collect();
+        apply_threshold(&mut heights, image_width as usize, stacking, threshold);
+        radii_vector = heights
+            .iter()
+            .map(|height| bottom_radius + height * relief_depth)
+            .collect();
+    }
+    let terracing = if let Some(&levels) = matches.get_one::<u32>("terraces") {
+        Some(Terracing::Levels(levels))
+    } else {
+        matches
+            .get_one::<f64>("layer_height")
+            .map(|&height| Terracing::LayerHeight(height))
+    };
+    let terraces = match terracing {
+        Some(terracing) => {
+            let terraces = Terraces::new(terracing, relief_depth)?;
+            terraces.apply(&mut radii_vector, surface_radius);
+            Some(terraces)
+        }
+        None => None,
+    };
+    if let Some(&min_feature) = matches.get_one::<f64>("min_feature") {
+        ensure!(
+            min_feature.is_finite() && min_feature > 0.0,
+            "Minimal feature size should be finite and greater than zero"
+        );
+        // Deviations from smoothed relief below this fraction of relief depth are ignored
+        const FEATURE_TOLERANCE: f64 = 0.25;
+        // Element wider than image would only wrap around it again
+        let radius = (
+            element_radius(min_feature / grid_step.0).min(image_width as usize),
+            element_radius(min_feature / grid_step.1).min(image_height as usize),
+        );
+        let (width, height) = (image_width as usize, image_height as usize);
+        let features = find_thin_features(
+            &radii_vector,
+            width,
+            height,
+            stacking,
+            radius,
+            FEATURE_TOLERANCE * relief_depth,
+        );
+        let action = FeatureAction::from_name(matches.get_one("min_feature_action"));
+        let removed = matches!(action, FeatureAction::Remove);
+        if removed {
+            remove_thin_features(&mut radii_vector, width, height, stacking, radius);
+        }
+        report_thin_features(&features, min_feature, grid_step, removed);
+    }
     let roller_end = if matches.contains_id("pin_diameter") {
         let pin_diameter = *matches.get_one::<f64>("pin_diameter").unwrap();
         let pin_length = *matches.get_one::<f64>("pin_length").unwrap();
         ensure!(pin_length > 0.0, "Pin length should be greater than zero");
         ensure!(
-            diameter - 2.0 * relief_depth > pin_diameter,
+            2.0 * bottom_radius > pin_diameter,
             "Pin diameter ({}) is too big (should be < {})",
             pin_diameter,
-            diameter - 2.0 * relief_depth
+            2.0 * bottom_radius
         );
         RollerEnd::Pin {
             pin_diameter: pin_diameter,
             pin_length: pin_length,
-            circle_points: (TAU * pin_diameter / grid_step).round() as u32,
+            circle_points: (TAU * pin_diameter / grid_step.0).round() as u32,
         }
     } else if matches.contains_id("channel_diameter") {
         let channel_diameter = *matches.get_one::<f64>("channel_diameter").unwrap();
         ensure!(
-            diameter - 2.0 * relief_depth > channel_diameter,
+            2.0 * bottom_radius > channel_diameter,
             "Channel diameter ({}) is too big (should be < {})",
-            diameter,
-            channel_diameter - 2.0 * relief_depth
+            channel_diameter,
+            2.0 * bottom_radius
         );
         RollerEnd::Channel {
             channel_diameter: channel_diameter,
-            circle_points: (TAU * channel_diameter / grid_step).round() as u32,
+            circle_points: (TAU * channel_diameter / grid_step.0).round() as u32,
         }
     } else {
         RollerEnd::Flat
@@ -215,40 +825,119 @@ fn parse_macthes(matches: ArgMatches, raw_image: DynamicImage) -> Result<Paramet
             .unwrap()
             .clone()
     } else {
-        let mut default_filename = matches.get_one::<String>("filename").unwrap().clone();
-        default_filename.push_str(".stl");
-        default_filename
+        default_output_filename(&matches)
     };
-    Ok(Parameters {
+    let mut parameters = Parameters {
         output_filename: output_filename,
+        validate: matches.get_flag("validate"),
         radii_vector: radii_vector,
         image_width: image_width,
         image_height: image_height,
         stack_horizontal: stack_horizontal,
         stack_vertical: stack_vertical,
+        stack_horizontal_rule,
+        stack_vertical_rule,
         roller_diameter: diameter,
         roller_length: length,
         relief_depth: relief_depth,
-        grid_step: grid_step,
+        relief_direction,
+        circumferential_step: grid_step.0,
+        axial_step: grid_step.1,
+        anisotropic_grid: matches
+            .get_one::<(f64, f64)>("grid_step")
+            .map_or(false, |step| step.0 != step.1),
+        mirrored_for_imprint,
+        crisp_walls: matches.get_flag("crisp_walls") || matches.contains_id("threshold"),
+        terraces,
+        normal_displacement: None,
+        split_strategy: SplitStrategy::from_name(matches.get_one("quad_split")),
+        subdivision: None,
+        adaptive_mesh: None,
         roller_end: roller_end,
-    })
+    };
+    if let Some(&factor) = matches.get_one::<u32>("subdivide") {
+        ensure!(
+            !parameters.crisp_walls,
+            "Subdivision cannot be combined with crisp walls"
+        );
+        // Interpolated vertices would lie between terrace levels
+        ensure!(
+            parameters.terraces.is_none(),
+            "Subdivision cannot be combined with terraces"
+        );
+        // Radii grid is allocated only when resulting faces can be counted
+        let faces = Subdivision::grid_size(
+            parameters.surface_width(),
+            parameters.surface_height(),
+            factor as usize,
+        )
+        .and_then(|(columns, rows)| columns.checked_mul(rows)?.checked_mul(2));
+        ensure!(
+            faces.map_or(false, |faces| faces <= u32::MAX as usize),
+            "Overflow in STL face counter: resulting model is too big"
+        );
+        parameters.subdivision = Some(Subdivision::new(&parameters, factor as usize));
+    }
+    if matches.get_flag("relief_along_normals") {
+        let smoothing = *matches.get_one::<f64>("normal_smoothing").unwrap_or(&1.0);
+        ensure!(
+            smoothing.is_finite() && smoothing >= 0.0,
+            "Normal smoothing should be finite and not negative"
+        );
+        parameters.normal_displacement = Some(NormalDisplacement::new(&parameters, smoothing)?);
+    }
+    if let Some(&tolerance) = matches.get_one::<f64>("adaptive_mesh") {
+        ensure!(
+            tolerance > 0.0,
+            "Adaptive mesh tolerance should be greater than zero"
+        );
+        parameters.adaptive_mesh = Some(AdaptiveMesh::new(&parameters, tolerance));
+    }
+    let seam_threshold = *matches.get_one::<f64>("seam_threshold").unwrap_or(&0.1);
+    check_seams(&parameters, seam_threshold);
+    Ok(parameters)
 }
 
 #[cfg(test)]
 fn test_cli_arguments(command_string: &str) -> Result<Parameters, anyhow::Error> {
     let image = DynamicImage::ImageRgb32F(Rgb32FImage::new(10, 10));
+    test_cli_arguments_with_image(command_string, image)
+}
+
+#[cfg(test)]
+fn test_cli_arguments_with_image(
+    command_string: &str,
+    image: DynamicImage,
+) -> Result<Parameters, anyhow::Error> {
     let arguments: Vec<&str> = command_string.split_whitespace().collect();
     let matches = cli_command().try_get_matches_from(arguments)?;
-    let parameters = parse_macthes(matches, image)?;
+    let parameters = parse_macthes(matches, InputImage::Raster(image))?;
     Ok(parameters)
 }
 
+// Raster image is generated from function of pixel position (x, y)
+#[cfg(test)]
+pub fn test_cli_arguments_with_pixels<P>(
+    command_string: &str,
+    (width, height): (u32, u32),
+    pixel: impl Fn(u32, u32) -> P,
+) -> Result<Parameters, anyhow::Error>
+where
+    P: image::Pixel,
+    DynamicImage: From<ImageBuffer<P, Vec<P::Subpixel>>>,
+{
+    let image = DynamicImage::from(ImageBuffer::from_fn(width, height, pixel));
+    test_cli_arguments_with_image(command_string, image)
+}
+
 #[test]
 fn test_conflicting_arguments() {
     let parameters = test_cli_arguments("img2roller -l 1 -d 1 test.png");
     assert!(parameters.is_err());
     let parameters = test_cli_arguments("img2roller -l 1 --pd 1 --pl 1 --cd 1 test.png");
     assert!(parameters.is_err());
+    let parameters = test_cli_arguments("img2roller -l 1 --text A --font a.ttf test.png");
+    assert!(parameters.is_err());
 }
 
 #[test]
@@ -259,6 +948,8 @@ fn test_missing_required_arguments() {
     assert!(parameters.is_err());
     let parameters = test_cli_arguments("img2roller -l 1 --pl 1 test.png");
     assert!(parameters.is_err());
+    let parameters = test_cli_arguments("img2roller -l 1 --text A");
+    assert!(parameters.is_err());
     let parameters = test_cli_arguments("img2roller -l 1 -p test.png");
     assert!(parameters.is_err());
 }
@@ -289,4 +980,471 @@ fn test_dimensions_arguments() {
     assert_eq!(parameters.roller_length, 10.0 * PI);
     let parameters = test_cli_arguments("img2roller -d 1 --sh 10 test.png").unwrap();
     assert_eq!(parameters.roller_length, PI * 0.1);
+    let error = test_cli_arguments("img2roller test.png")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("--diameter") && error.contains("--length"));
+}
+
+#[test]
+fn test_validate_arguments() {
+    let validate_stl = |command_string: &str| {
+        cli_command().try_get_matches_from(command_string.split_whitespace())
+    };
+    assert!(validate_stl("img2roller validate-stl test.stl").is_ok());
+    assert!(validate_stl("img2roller validate-stl test.stl -d 1").is_err());
+    assert!(validate_stl("img2roller -d 1 validate-stl test.stl").is_err());
+    assert!(validate_stl("img2roller validate-stl test.stl test.png").is_err());
+    assert!(validate_stl("img2roller validate-stl").is_err());
+}
+
+#[test]
+fn test_default_output_filename() {
+    let output_filename = |command_string: &str| {
+        let arguments = command_string.split_whitespace();
+        default_output_filename(&cli_command().try_get_matches_from(arguments).unwrap())
+    };
+    assert_eq!(output_filename("img2roller -d 1 test.png"), "test.png.stl");
+    assert_eq!(
+        output_filename("img2roller -d 1 -l 2 --pattern ribs:1"),
+        "ribs.stl"
+    );
+    let command_string = "img2roller -d 1 --band parts/top.png:2 --band bottom.svg";
+    assert_eq!(output_filename(command_string), "parts/top.stl");
+    assert_eq!(output_filename("img2roller -d 1 --segment a.svg"), "a.stl");
+    assert_eq!(
+        output_filename("img2roller -d 1 --text A --font f.ttf"),
+        "text.stl"
+    );
+}
+
+#[test]
+fn test_stack_rules() {
+    let arguments = "img2roller -d 1 --sh 3 --sv 2 --shr mirror --svr rotate test.png";
+    let parameters = test_cli_arguments_with_pixels(arguments, (4, 3), |x, y| {
+        Luma([{ 1000 * (x + 4 * y) } as u16])
+    })
+    .unwrap();
+    assert_eq!(parameters.get_rho(4, 1), parameters.get_rho(3, 1));
+    assert_eq!(parameters.get_rho(8, 1), parameters.get_rho(0, 1));
+    assert_eq!(parameters.get_rho(0, 3), parameters.get_rho(3, 2));
+    assert_eq!(parameters.get_rho(4, 3), parameters.get_rho(0, 2));
+    assert_eq!(parameters.get_rho_looped(-1, 0), parameters.get_rho(3, 0));
+    let parameters = test_cli_arguments("img2roller -d 1 --shr mirror test.png");
+    assert!(parameters.is_err());
+}
+
+#[test]
+fn test_seam_blend_stack_rules() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 3), |x, y| {
+            Luma([{ 1000 * (x + 4 * y) } as u16])
+        })
+        .unwrap()
+    };
+    let plain = parameters("img2roller -d 1 --sh 2 --shr mirror test.png");
+    let blended = parameters("img2roller -d 1 --sh 2 --shr mirror --seam-blend 1 test.png");
+    assert_eq!(blended.radii_vector, plain.radii_vector);
+    let blended = parameters("img2roller -d 1 --sh 3 --shr mirror --seam-blend 1 test.png");
+    for j in 0..3 {
+        assert_eq!(blended.get_rho_looped(-1, j), blended.get_rho_looped(0, j));
+    }
+    assert_ne!(blended.get_rho(3, 0), blended.get_rho(3, 1));
+    // Blended seams are thresholded together with rest of image
+    let arguments = "img2roller -d 1 -e 0.25 --sh 3 --seam-blend 1 --threshold 0.5 test.png";
+    assert!(parameters(arguments)
+        .radii_vector
+        .iter()
+        .all(|rho| *rho == 0.25 || *rho == 0.5));
+    assert!(test_cli_arguments("img2roller -d 1 --seam-threshold 0 test.png").is_ok());
+    assert!(test_cli_arguments("img2roller -d 1 --seam-threshold nan test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 1 --seam-threshold=-0.1 test.png").is_err());
+}
+
+#[test]
+fn test_mirror_for_imprint() {
+    let arguments = "img2roller -d 1 --mirror-for-imprint test.png";
+    let parameters =
+        test_cli_arguments_with_pixels(arguments, (4, 3), |x, _| Luma([{ 1000 * x } as u16]))
+            .unwrap();
+    assert!(parameters.get_rho(0, 0) > parameters.get_rho(3, 0));
+}
+
+#[test]
+fn test_transparent_level() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 1), |x, _| {
+            LumaA([{ 1000 * x } as u16, if x == 0 { 0 } else { u16::MAX }])
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.5 -t surface test.png";
+    assert_eq!(parameters(arguments).unwrap().get_rho(0, 0), 1.0);
+    let arguments = "img2roller -d 2 -e 0.5 -t 0.1 test.png";
+    assert_eq!(parameters(arguments).unwrap().get_rho(0, 0), 0.9);
+    assert!(parameters("img2roller -d 2 -e 0.5 -t 0.6 test.png").is_err());
+}
+
+#[test]
+fn test_height_source() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (3, 1), |x, _| match x {
+            0 => Rgb([0, 0, u16::MAX]),
+            1 => Rgb([u16::MAX, 0, 0]),
+            _ => Rgb([u16::MAX, u16::MAX, u16::MAX]),
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.5 --height-source red test.png";
+    assert_eq!(
+        parameters(arguments).unwrap().radii_vector,
+        vec![0.5, 1.0, 1.0]
+    );
+    let arguments = "img2roller -d 2 -e 0.5 --height-source mix:0,0,1 test.png";
+    assert_eq!(
+        parameters(arguments).unwrap().radii_vector,
+        vec![1.0, 0.5, 1.0]
+    );
+    assert!(parameters("img2roller -d 2 --height-source mix:0,0 test.png").is_err());
+    assert!(parameters("img2roller -d 2 --height-source mix:0,0,0 test.png").is_err());
+}
+
+#[test]
+fn test_absolute_levels() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (3, 1), |x, _| Luma([{ 16384 * (x + 1) } as u16]))
+    };
+    let arguments = "img2roller -d 2 -e 0.5 --absolute-levels test.png";
+    assert!((parameters(arguments).unwrap().radii_vector[0] - 0.625).abs() < 1e-4);
+    let arguments = "img2roller -d 2 -e 0.5 --input-levels 0.3,0.5 test.png";
+    assert_eq!(parameters(arguments).unwrap().radii_vector[1..], [1.0, 1.0]);
+    assert!(parameters("img2roller -d 2 --input-levels 0.5,0.3 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -a --input-levels 0.3,0.5 test.png").is_err());
+}
+
+#[test]
+fn test_tone_arguments() {
+    let parameters = |arguments: &str| {
+        test_cli_arguments_with_pixels(arguments, (3, 1), |x, _| Luma([{ 16384 * x } as u16]))
+    };
+    let arguments = "img2roller -d 2 -e 0.5 -a --gamma 0.5 --output-levels 0.5,1 test.png";
+    assert!((parameters(arguments).unwrap().radii_vector[1] - 0.765625).abs() < 1e-4);
+    let arguments = "img2roller -d 2 -e 0.5 --tone-curve 0:1,1:0 test.png";
+    assert_eq!(
+        parameters(arguments).unwrap().radii_vector,
+        vec![1.0, 0.75, 0.5]
+    );
+    for tone in [
+        "--tone-curve 0.5:1,0.2:0",
+        "--gamma 0",
+        "--gamma nan",
+        "--contrast=-1",
+        "--contrast inf",
+        "--contrast nan",
+    ] {
+        let arguments = format!("img2roller -d 2 {} test.png", tone);
+        assert!(parameters(&arguments).is_err());
+    }
+}
+
+#[test]
+fn test_threshold_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 1), |x, _| Luma([{ 16384 * x } as u16]))
+    };
+    // Threshold implies crisp walls
+    let thresholded = parameters("img2roller -d 2 -e 0.5 --threshold 0.4 test.png").unwrap();
+    assert_eq!(thresholded.vertex_columns(), 8);
+    assert_eq!(thresholded.get_vertex_rho(3, 1), 0.5);
+    assert_eq!(thresholded.get_vertex_rho(4, 1), 1.0);
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold otsu test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold adaptive:1 test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold 1.5 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold adaptive:x test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold adaptive:0 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold adaptive:4 test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -e 0.5 --threshold adaptive:5 test.png").is_err());
+}
+
+#[test]
+fn test_terraces_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (5, 1), |x, _| Luma([{ 16000 * x } as u16]))
+    };
+    let terraced = parameters("img2roller -d 2 -e 0.5 --terraces 3 test.png").unwrap();
+    let terraces = terraced.terraces.as_ref().unwrap();
+    assert_eq!((terraces.levels, terraces.step), (3, 0.25));
+    assert_eq!(terraced.radii_vector[2], 0.75);
+    assert!(parameters("img2roller -d 2 --terraces 3 --layer-height 0.1 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --layer-height 0.6 test.png").is_err());
+}
+
+#[test]
+fn test_filter_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (6, 6), |x, _| {
+            Luma([if x < 3 { 0 } else { u16::MAX }])
+        })
+    };
+    let blurred = parameters("img2roller -d 2 -e 0.5 -f blur:1 test.png").unwrap();
+    assert!((blurred.get_rho(0, 0) - blurred.get_rho(2, 5)).abs() < 1e-6);
+    assert!(blurred.get_rho(1, 0) < blurred.get_rho(2, 0));
+    assert!(parameters("img2roller -d 2 -e 0.5 -f canny -f median:1 test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -f blur:0 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -f canny:0.5:0.2 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -f blur:2 test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -f blur:inf test.png").is_err());
+    assert!(parameters("img2roller -d 2 -f blur:2.5 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -f median:1e12 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -f sharpen:1:nan test.png").is_err());
+}
+
+#[test]
+fn test_min_feature_arguments() {
+    // One pixel wide ridge around roller
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (8, 8), |x, _| {
+            Luma([if x == 3 { u16::MAX } else { 0 }])
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.5 --min-feature 2 test.png";
+    assert!(parameters(arguments).unwrap().radii_vector.contains(&1.0));
+    let arguments = "img2roller -d 2 -e 0.5 --min-feature 2 --min-feature-action remove test.png";
+    assert!(parameters(arguments)
+        .unwrap()
+        .radii_vector
+        .iter()
+        .all(|&rho| rho == 0.5));
+    assert!(parameters("img2roller -d 2 --min-feature 0 test.png").is_err());
+    assert!(parameters("img2roller -d 2 --min-feature inf test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --min-feature 1e9 test.png").is_ok());
+    assert!(parameters("img2roller -d 2 --min-feature-action remove test.png").is_err());
+}
+
+#[test]
+fn test_relief_along_normals_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (8, 8), |x, _| {
+            Luma([if (2..5).contains(&x) { u16::MAX } else { 0 }])
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.1 --relief-along-normals test.png";
+    assert!(parameters(arguments).unwrap().normal_displacement.is_some());
+    let arguments =
+        "img2roller -d 2 -e 0.5 --crisp-walls --relief-along-normals --normal-smoothing 0 test.png";
+    assert!(parameters(arguments).is_err());
+    assert!(parameters("img2roller -d 2 --normal-smoothing 1 test.png").is_err());
+    let arguments = "img2roller -d 2 -e 0.1 --relief-along-normals --normal-smoothing inf test.png";
+    assert!(parameters(arguments).is_err());
+}
+
+#[test]
+fn test_relief_direction_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (2, 1), |x, _| {
+            Luma([{ u16::MAX as u32 * x } as u16])
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.5 --relief-direction outward test.png";
+    let parameters_outward = parameters(arguments).unwrap();
+    assert_eq!(parameters_outward.radii_vector, vec![1.0, 1.5]);
+    assert_eq!(parameters_outward.surface_radius(), 1.5);
+    let arguments = "img2roller -d 2 -e 0.5 --relief-direction centered test.png";
+    assert_eq!(
+        parameters(arguments).unwrap().radii_vector,
+        vec![0.75, 1.25]
+    );
+    // Centered relief may be deeper than roller radius, inward one can not
+    assert!(parameters("img2roller -d 2 -e 1.5 --relief-direction centered test.png").is_ok());
+    assert!(parameters("img2roller -d 2 -e 1.5 test.png").is_err());
+}
+
+#[test]
+fn test_relief_direction_radius_range() {
+    assert_eq!(ReliefDirection::Inward.radius_range(2.0, 0.5), (1.5, 2.0));
+    assert_eq!(ReliefDirection::Outward.radius_range(2.0, 0.5), (2.0, 2.5));
+    assert_eq!(
+        ReliefDirection::Centered.radius_range(2.0, 0.5),
+        (1.75, 2.25)
+    );
+}
+
+#[test]
+fn test_pattern_arguments() {
+    let parameters = |arguments: &str| {
+        let matches = cli_command().try_get_matches_from(arguments.split_whitespace())?;
+        Parameters::parse_arguments_and_file(matches)
+    };
+    let arguments = "img2roller -d 2 -l 3 -g 0.1 --pattern ribs:0.5:45 --sh 2";
+    let parameters_ribs = parameters(arguments).unwrap();
+    assert_eq!(parameters_ribs.roller_length, 3.0);
+    assert_eq!(parameters_ribs.surface_width(), 62);
+    assert!(parameters("img2roller -d 2 --pattern dots:1").is_err());
+    assert!(parameters("img2roller -d 2 --pattern knurl:0").is_err());
+    assert!(parameters("img2roller -d 2 -l 3 --pattern noise:1e-9").is_err());
+    assert!(parameters("img2roller -d 2 -l 3 --pattern dots:1:1e-300").is_err());
+    // Too coarse grid leaves fewer than 3 columns around circumference
+    assert!(parameters("img2roller -d 1 -l 1 --pattern ribs:100").is_err());
+}
+
+#[test]
+fn test_layer_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (2, 1), |x, _| {
+            Luma([{ u16::MAX as u32 * x } as u16])
+        })
+    };
+    let arguments = "img2roller -d 2 -e 0.5 --layer mask:0:pattern:ribs:1 test.png";
+    assert_eq!(parameters(arguments).unwrap().radii_vector, vec![0.5, 1.0]);
+    // Threshold is applied to heights combined with layers
+    let arguments =
+        "img2roller -d 2 -e 0.5 --threshold 0.5 --layer max:0.8:pattern:ribs:1 test.png";
+    assert!(parameters(arguments)
+        .unwrap()
+        .radii_vector
+        .iter()
+        .all(|rho| *rho == 0.5 || *rho == 1.0));
+    assert!(parameters("img2roller -d 2 -e 0.5 --layer max:text:A test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --layer blend:image:a.png test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --layer max:-1:image:a.png test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --layer max:nan:pattern:ribs:2 test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --layer max:inf:image:a.png test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --layer max:shape:a test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --font a.ttf test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --seed 5 test.png").is_err());
+    let arguments =
+        "img2roller -d 2 --seed 5 --pattern-profile square --layer max:pattern:noise:1 test.png";
+    assert!(test_cli_arguments(arguments).is_ok());
+}
+
+#[test]
+fn test_float_heights() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (3, 1), |x, _| Rgb([{ 0.3 * x as f32 }; 3]))
+    };
+    let parameters_mm = parameters("img2roller -d 2 --heights-in-mm test.png").unwrap();
+    assert!((parameters_mm.relief_depth - 0.6).abs() < 1e-6);
+    assert!((parameters_mm.radii_vector[1] - 0.7).abs() < 1e-6);
+    let parameters_mm = parameters("img2roller -d 2 -e 0.3 --heights-in-mm test.png").unwrap();
+    assert!((parameters_mm.radii_vector[0] - 0.7).abs() < 1e-6);
+    assert_eq!(parameters_mm.radii_vector[1..], [1.0, 1.0]);
+    let arguments = "img2roller -d 2 -e 0.5 --input-levels 0.3,0.6 test.png";
+    assert!((parameters(arguments).unwrap().radii_vector[1] - 0.5).abs() < 1e-6);
+    // Integer image has no heights in mm
+    let arguments = "img2roller -d 2 --heights-in-mm test.png";
+    assert!(test_cli_arguments_with_pixels(arguments, (3, 1), |_, _| Luma([0u16])).is_err());
+    // Millimetres are not remapped by tone adjustments
+    assert!(test_cli_arguments("img2roller -d 2 -e 0.5 --heights-in-mm test.r32").is_ok());
+    for tone in [
+        "--gamma 2",
+        "--contrast 2",
+        "--output-levels 0,0.5",
+        "--tone-curve 0:1,1:0",
+        "--tone-lut a.lut",
+    ] {
+        let arguments = format!("img2roller -d 2 -e 0.5 --heights-in-mm {} test.r32", tone);
+        assert!(test_cli_arguments(&arguments).is_err());
+    }
+}
+
+#[test]
+fn test_transform_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 2), |x, _| {
+            Luma([{ 21845 * x.min(3) } as u16])
+        })
+    };
+    let arguments = "img2roller -d 2 --rotate 90 --flip vertical test.png";
+    assert_eq!(parameters(arguments).unwrap().surface_width(), 2);
+    let arguments = "img2roller -d 2 -e 0.5 --crop 1,0,3,2 --pad 0,1 test.png";
+    let parameters_cropped = parameters(arguments).unwrap();
+    assert_eq!(parameters_cropped.surface_width(), 5);
+    assert_eq!(
+        parameters_cropped.radii_vector[..5],
+        [1.0, 0.5, 0.75, 1.0, 1.0]
+    );
+    assert!(parameters("img2roller -d 2 --pad 0,4 test.png").is_err());
+    assert!(parameters("img2roller -d 2 --crop 2,0,3,2 test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --pad 1,2,3 test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --rotate nan test.png").is_err());
+    assert!(test_cli_arguments("img2roller -d 2 --rotate inf test.png").is_err());
+}
+
+#[test]
+fn test_independent_grid_steps() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 2), |x, _| {
+            Luma([{ 21845 * x.min(3) } as u16])
+        })
+    };
+    let parameters_anisotropic = parameters("img2roller -d 2 -g 0.5,1 test.png").unwrap();
+    assert_eq!(
+        (
+            parameters_anisotropic.image_width,
+            parameters_anisotropic.image_height
+        ),
+        (13, 3)
+    );
+    assert_eq!(parameters_anisotropic.circumferential_step, 0.5);
+    assert!(parameters_anisotropic.anisotropic_grid);
+    let parameters_uniform = parameters("img2roller -d 2 -g 0.5 test.png").unwrap();
+    assert_eq!(
+        (
+            parameters_uniform.image_width,
+            parameters_uniform.image_height
+        ),
+        (13, 6)
+    );
+    assert!(!parameters_uniform.anisotropic_grid);
+    assert!(parameters("img2roller -d 2 -g 0.5,0 test.png").is_err());
+}
+
+#[test]
+fn test_adaptive_mesh_arguments() {
+    // Flat image with single bump
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (16, 8), |x, y| {
+            Luma([if (x, y) == (5, 3) { u16::MAX } else { 0 }])
+        })
+    };
+    let parameters_mesh =
+        parameters("img2roller -d 2 -e 0.5 --adaptive-mesh 0.5 test.png").unwrap();
+    let mesh = parameters_mesh.adaptive_mesh.as_ref().unwrap();
+    let faces = parameters_mesh.faces_count().unwrap() as usize;
+    assert_eq!(faces, mesh.triangles.len() + 2 * 16);
+    assert!(parameters("img2roller -d 2 --adaptive-mesh 0 test.png").is_err());
+}
+
+#[test]
+fn test_subdivide_arguments() {
+    let parameters = |arguments| {
+        test_cli_arguments_with_pixels(arguments, (4, 3), |x, y| {
+            Luma([{ 21845 * ((x + y) % 4).min(3) } as u16])
+        })
+    };
+    let subdivided = parameters("img2roller -d 2 -e 0.5 --subdivide 2 test.png").unwrap();
+    assert_eq!(
+        (subdivided.vertex_columns(), subdivided.vertex_rows()),
+        (8, 5)
+    );
+    assert_eq!(subdivided.vertex_position(3), 1.5);
+    assert_eq!(subdivided.get_vertex_rho(4, 2), subdivided.get_rho(2, 1));
+    let between = subdivided.get_vertex_rho(3, 2);
+    assert!(between > subdivided.get_rho(1, 1) && between < subdivided.get_rho(2, 1));
+    assert_eq!(subdivided.faces_count().unwrap(), 2 * 8 * 4 + 2 * 8);
+    assert!(parameters("img2roller -d 2 --subdivide 2 --threshold 0.5 test.png").is_err());
+    assert!(parameters("img2roller -d 2 -e 0.5 --subdivide 2 --terraces 3 test.png").is_err());
+    let arguments = "img2roller -d 2 -e 0.5 --subdivide 2 --layer-height 0.25 test.png";
+    assert!(parameters(arguments).is_err());
+    assert!(parameters("img2roller -d 2 --subdivide 0 test.png").is_err());
+    assert!(parameters("img2roller -d 2 --subdivide 100000 test.png").is_err());
+}
+
+#[test]
+fn test_quad_split_arguments() {
+    let parameters = test_cli_arguments("img2roller -d 2 --quad-split alternating test.png");
+    assert!(matches!(
+        parameters.unwrap().split_strategy,
+        SplitStrategy::Alternating
+    ));
+    assert!(test_cli_arguments("img2roller -d 2 --quad-split random test.png").is_err());
 }
@@ -0,0 +1,277 @@
+use image::{DynamicImage, ImageBuffer, Luma};
+use std::f64::consts::{PI, TAU};
+
+// Sizes are in mm, angles are in degrees from roller axis
+#[derive(Clone, Copy)]
+pub enum Pattern {
+    Ribs { pitch: f64, angle: f64 },
+    Knurl { pitch: f64, angle: f64 },
+    Dots { pitch: f64, diameter: f64 },
+    Noise { scale: f64 },
+    Voronoi { cell: f64 },
+}
+
+impl Pattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Ribs { .. } => "ribs",
+            Pattern::Knurl { .. } => "knurl",
+            Pattern::Dots { .. } => "dots",
+            Pattern::Noise { .. } => "noise",
+            Pattern::Voronoi { .. } => "voronoi",
+        }
+    }
+
+    // Distance between pixels resolving smallest pattern feature
+    pub fn default_grid_step(&self) -> f64 {
+        const PIXELS_PER_FEATURE: f64 = 10.0;
+        let feature = match *self {
+            Pattern::Ribs { pitch, .. } | Pattern::Knurl { pitch, .. } => pitch,
+            Pattern::Dots { pitch, diameter } => pitch.min(diameter),
+            Pattern::Noise { scale } => scale,
+            Pattern::Voronoi { cell } => cell,
+        };
+        feature / PIXELS_PER_FEATURE
+    }
+}
+
+// Cross-section of ribs and dots from bottom (0) to top (1) and back
+#[derive(Clone, Copy)]
+pub enum Profile {
+    Sine,
+    Triangle,
+    Square,
+}
+
+impl Profile {
+    pub fn from_name(name: Option<&String>) -> Profile {
+        match name.map(String::as_str) {
+            Some("triangle") => Profile::Triangle,
+            Some("square") => Profile::Square,
+            _ => Profile::Sine,
+        }
+    }
+
+    // Height at given phase of period
+    fn wave(&self, phase: f64) -> f64 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            Profile::Sine => 0.5 - 0.5 * (TAU * phase).cos(),
+            Profile::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            Profile::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    // Height at given distance from dot center relative to its radius
+    fn dome(&self, distance: f64) -> f64 {
+        if distance >= 1.0 {
+            return 0.0;
+        }
+        match self {
+            Profile::Sine => 0.5 + 0.5 * (PI * distance).cos(),
+            Profile::Triangle => 1.0 - distance,
+            Profile::Square => 1.0,
+        }
+    }
+}
+
+// Height field generated on surface of one image copy, periods around circumference
+// and along axis are adjusted to whole numbers, so that stacked copies are seamless
+pub struct PatternImage {
+    pub pattern: Pattern,
+    pub profile: Profile,
+    pub seed: u64,
+    pub circumference: f64,
+    pub length: f64,
+    pub grid_step: (f64, f64),
+}
+
+impl PatternImage {
+    pub fn dimensions(&self) -> (u32, u32) {
+        (
+            { (self.circumference / self.grid_step.0).round() as u32 }.max(1),
+            { (self.length / self.grid_step.1).round() as u32 }.max(2),
+        )
+    }
+
+    pub fn render(&self, width: u32, height: u32) -> DynamicImage {
+        let step_x = self.circumference / { width as f64 };
+        let step_z = self.length / { height as f64 };
+        let image = ImageBuffer::from_fn(width, height, |i, j| {
+            let x = ({ i as f64 } + 0.5) * step_x;
+            let z = ({ j as f64 } + 0.5) * step_z;
+            let value = self.height(x, z).clamp(0.0, 1.0);
+            Luma([(value * { u16::MAX as f64 }).round() as u16])
+        });
+        DynamicImage::ImageLuma16(image)
+    }
+
+    // Whole number of features of given size around circumference and along axis
+    fn periods(&self, size: f64) -> (i64, i64) {
+        (
+            { (self.circumference / size).round() as i64 }.max(1),
+            { (self.length / size).round() as i64 }.max(1),
+        )
+    }
+
+    // Phase of ribs crossing circumference and copy length whole number of times
+    fn rib_phase(&self, x: f64, z: f64, pitch: f64, angle: f64) -> f64 {
+        let (sin_angle, cos_angle) = angle.to_radians().sin_cos();
+        let periods_x = (self.circumference * cos_angle / pitch).round();
+        let periods_z = (self.length * sin_angle / pitch).round();
+        x * periods_x / self.circumference + z * periods_z / self.length
+    }
+
+    fn height(&self, x: f64, z: f64) -> f64 {
+        match self.pattern {
+            Pattern::Ribs { pitch, angle } => self.profile.wave(self.rib_phase(x, z, pitch, angle)),
+            Pattern::Knurl { pitch, angle } => {
+                let first = self.profile.wave(self.rib_phase(x, z, pitch, angle));
+                let second = self.profile.wave(self.rib_phase(x, z, pitch, -angle));
+                first.min(second)
+            }
+            Pattern::Dots { pitch, diameter } => {
+                let (periods_x, periods_z) = self.periods(pitch);
+                let pitch_x = self.circumference / { periods_x as f64 };
+                let pitch_z = self.length / { periods_z as f64 };
+                let dx = ((x / pitch_x).rem_euclid(1.0) - 0.5) * pitch_x;
+                let dz = ((z / pitch_z).rem_euclid(1.0) - 0.5) * pitch_z;
+                self.profile.dome(dx.hypot(dz) / (0.5 * diameter))
+            }
+            Pattern::Noise { scale } => 0.5 + self.gradient_noise(x, z, scale),
+            Pattern::Voronoi { cell } => self.voronoi_edges(x, z, cell),
+        }
+    }
+
+    // Perlin noise on lattice looped around circumference and along axis
+    fn gradient_noise(&self, x: f64, z: f64, scale: f64) -> f64 {
+        let (cells_x, cells_z) = self.periods(scale);
+        let u = x / (self.circumference / { cells_x as f64 });
+        let v = z / (self.length / { cells_z as f64 });
+        let (i, j) = (u.floor() as i64, v.floor() as i64);
+        let (fu, fv) = (u - { i as f64 }, v - { j as f64 });
+        let corner = |di: i64, dj: i64| {
+            let (ci, cj) = ((i + di).rem_euclid(cells_x), (j + dj).rem_euclid(cells_z));
+            let angle = TAU * self.random(ci, cj, 0);
+            angle.cos() * (fu - { di as f64 }) + angle.sin() * (fv - { dj as f64 })
+        };
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let (su, sv) = (fade(fu), fade(fv));
+        let top = corner(0, 0) + su * (corner(1, 0) - corner(0, 0));
+        let bottom = corner(0, 1) + su * (corner(1, 1) - corner(0, 1));
+        top + sv * (bottom - top)
+    }
+
+    // Cells are raised, their borders (equal distance to two nearest points) are grooves
+    fn voronoi_edges(&self, x: f64, z: f64, cell: f64) -> f64 {
+        let (cells_x, cells_z) = self.periods(cell);
+        let cell_x = self.circumference / { cells_x as f64 };
+        let cell_z = self.length / { cells_z as f64 };
+        let (i, j) = ((x / cell_x).floor() as i64, (z / cell_z).floor() as i64);
+        let (mut nearest, mut second) = (f64::MAX, f64::MAX);
+        for dj in -1..=1 {
+            for di in -1..=1 {
+                let (ci, cj) = (i + di, j + dj);
+                let (wrapped_i, wrapped_j) = (ci.rem_euclid(cells_x), cj.rem_euclid(cells_z));
+                let point_x = ({ ci as f64 } + self.random(wrapped_i, wrapped_j, 1)) * cell_x;
+                let point_z = ({ cj as f64 } + self.random(wrapped_i, wrapped_j, 2)) * cell_z;
+                let distance = (x - point_x).hypot(z - point_z);
+                if distance < nearest {
+                    (nearest, second) = (distance, nearest);
+                } else if distance < second {
+                    second = distance;
+                }
+            }
+        }
+        ((second - nearest) / (0.25 * cell)).min(1.0)
+    }
+
+    // Uniform value from 0 to 1 for lattice node, deterministic for given seed
+    fn random(&self, i: i64, j: i64, channel: u64) -> f64 {
+        let mut state = self.seed
+            ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (j as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ channel.wrapping_mul(0x1656_67B1_9E37_79F9);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_pattern_is_seamless() {
+    for pattern in [
+        Pattern::Ribs {
+            pitch: 3.0,
+            angle: 20.0,
+        },
+        Pattern::Knurl {
+            pitch: 2.0,
+            angle: 30.0,
+        },
+        Pattern::Dots {
+            pitch: 4.0,
+            diameter: 2.0,
+        },
+        Pattern::Noise { scale: 5.0 },
+        Pattern::Voronoi { cell: 5.0 },
+    ] {
+        let pattern_image = PatternImage {
+            pattern,
+            profile: Profile::Sine,
+            seed: 7,
+            circumference: 31.0,
+            length: 10.0,
+            grid_step: (0.5, 0.5),
+        };
+        for z in [0.0, 1.3, 4.7] {
+            let start = pattern_image.height(0.0, z);
+            let end = pattern_image.height(31.0, z);
+            assert!((start - end).abs() < 1e-9);
+        }
+        for x in [0.0, 2.9, 17.5] {
+            let top = pattern_image.height(x, 0.0);
+            let bottom = pattern_image.height(x, 10.0);
+            assert!((top - bottom).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn test_profiles() {
+    assert_eq!(Profile::Triangle.wave(0.25), 0.5);
+    assert_eq!(Profile::Sine.wave(0.5), 1.0);
+    assert_eq!(Profile::Square.wave(0.75), 0.0);
+    assert_eq!(Profile::Triangle.dome(0.25), 0.75);
+    assert_eq!(Profile::Sine.dome(1.5), 0.0);
+}
+
+#[test]
+fn test_pattern_dimensions() {
+    let pattern_image = PatternImage {
+        pattern: Pattern::Ribs {
+            pitch: 0.5,
+            angle: 45.0,
+        },
+        profile: Profile::Sine,
+        seed: 0,
+        circumference: PI,
+        length: 3.0,
+        grid_step: (0.1, 0.1),
+    };
+    assert_eq!(pattern_image.dimensions(), (31, 30));
+    // At least one column and two rows even for step bigger than roller
+    let pattern_image = PatternImage {
+        grid_step: (10.0, 10.0),
+        ..pattern_image
+    };
+    assert_eq!(pattern_image.dimensions(), (1, 2));
+}
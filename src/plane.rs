@@ -0,0 +1,94 @@
+use crate::parameters::StackRule;
+use crate::seams::copy_flips;
+
+// Copies of image around circumference and along axis with their stack rules,
+// both given as (horizontal, vertical)
+#[derive(Clone, Copy)]
+pub struct Stacking {
+    pub rules: (StackRule, StackRule),
+    pub copies: (usize, usize),
+}
+
+impl Stacking {
+    // Whole roller surface is looped around circumference only
+    pub const SURFACE: Stacking = Stacking {
+        rules: (StackRule::Repeat, StackRule::Repeat),
+        copies: (1, 1),
+    };
+}
+
+// Single image channel as seen from first copy on roller surface, pixels beyond image edges
+// are taken from neighbouring copies (flipped by stack rules), horizontal edges are always
+// looped (image is wrapped around roller), vertical ones only if copies are stacked vertically
+#[derive(Clone)]
+pub struct Plane {
+    pub width: usize,
+    pub height: usize,
+    pub stacking: Stacking,
+    pub values: Vec<f64>,
+}
+
+impl Plane {
+    pub fn looped_vertically(&self) -> bool {
+        self.stacking.copies.1 > 1
+    }
+
+    // Index of pixel at given position, none beyond roller ends
+    pub fn index(&self, i: isize, j: isize) -> Option<usize> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let (copies_h, copies_v) = self.stacking.copies;
+        if !self.looped_vertically() && (j < 0 || j >= height) {
+            return None;
+        }
+        let p = i.div_euclid(width).rem_euclid(copies_h as isize) as usize;
+        let q = j.div_euclid(height).rem_euclid(copies_v as isize) as usize;
+        let (flip_columns, flip_rows) = copy_flips(self.stacking.rules, (p, q));
+        let (i, j) = (i.rem_euclid(width), j.rem_euclid(height));
+        let i = if flip_columns { width - 1 - i } else { i };
+        let j = if flip_rows { height - 1 - j } else { j };
+        Some({ j * width + i } as usize)
+    }
+
+    // Rows beyond roller ends are replicated
+    pub fn get(&self, i: isize, j: isize) -> f64 {
+        let j = if self.looped_vertically() {
+            j
+        } else {
+            j.clamp(0, self.height as isize - 1)
+        };
+        self.values[self.index(i, j).unwrap()]
+    }
+
+    pub fn map_pixels(&self, function: impl Fn(isize, isize) -> f64) -> Plane {
+        let values = (0..self.height as isize)
+            .flat_map(|j| (0..self.width as isize).map(move |i| (i, j)))
+            .map(|(i, j)| function(i, j))
+            .collect();
+        Plane { values, ..*self }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_neighbours_follow_stack_rules() {
+    let plane = |rules, copies| Plane {
+        width: 3,
+        height: 2,
+        stacking: Stacking { rules, copies },
+        values: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+    };
+    let repeat = plane((StackRule::Repeat, StackRule::Repeat), (2, 1));
+    assert_eq!((repeat.get(-1, 0), repeat.get(3, 1)), (2.0, 3.0));
+    assert_eq!((repeat.get(0, -1), repeat.get(0, 2)), (0.0, 3.0));
+    assert_eq!(repeat.index(0, 2), None);
+    // Odd copy is mirrored, so edge columns meet themselves
+    let mirror = plane((StackRule::Mirror, StackRule::Repeat), (2, 1));
+    assert_eq!((mirror.get(-1, 0), mirror.get(3, 1)), (0.0, 5.0));
+    assert_eq!(mirror.get(4, 0), 1.0);
+    // With odd count last copy is not mirrored, so image wraps around at left edge
+    let mirror = plane((StackRule::Mirror, StackRule::Repeat), (3, 1));
+    assert_eq!((mirror.get(-1, 0), mirror.get(3, 0)), (2.0, 2.0));
+    // Rotated copy has its first row reversed next to last row of first copy
+    let rotate = plane((StackRule::Repeat, StackRule::Rotate), (1, 2));
+    assert_eq!((rotate.get(0, -1), rotate.get(0, 2)), (2.0, 5.0));
+}
@@ -0,0 +1,255 @@
+use crate::parameters::{Parameters, StackRule};
+
+#[derive(Clone, Copy)]
+pub enum SeamBlend {
+    Crossfade,
+    Feather,
+}
+
+impl SeamBlend {
+    pub fn from_name(name: Option<&String>) -> SeamBlend {
+        match name.map(String::as_str) {
+            Some("feather") => SeamBlend::Feather,
+            _ => SeamBlend::Crossfade,
+        }
+    }
+}
+
+// Image edges meeting at seam between neighbouring copies, given as first or last
+// line of image, positions along one of them are reversed if copies are flipped differently
+#[derive(Clone, Copy, PartialEq)]
+struct Seam {
+    last_a: bool,
+    last_b: bool,
+    reversed: bool,
+}
+
+// Flips of copy at given stack (column, row) as (columns, rows), same as used for surface
+pub fn copy_flips(rules: (StackRule, StackRule), (p, q): (usize, usize)) -> (bool, bool) {
+    let (flip_columns_h, flip_rows_h) = rules.0.flips(p);
+    let (flip_rows_v, flip_columns_v) = rules.1.flips(q);
+    (flip_columns_h != flip_columns_v, flip_rows_h != flip_rows_v)
+}
+
+// Distinct seams between copies, horizontal ones wrap around circumference,
+// edges of copies meeting their own mirror image are continuous and skipped
+fn find_seams(
+    rules: (StackRule, StackRule),
+    (copies_h, copies_v): (usize, usize),
+    horizontal: bool,
+) -> Vec<Seam> {
+    let neighbours: Vec<((usize, usize), (usize, usize))> = if horizontal {
+        (0..copies_v)
+            .flat_map(|q| (0..copies_h).map(move |p| ((p, q), ((p + 1) % copies_h, q))))
+            .collect()
+    } else {
+        (0..copies_h)
+            .flat_map(|p| (1..copies_v).map(move |q| ((p, q - 1), (p, q))))
+            .collect()
+    };
+    let mut seams = Vec::new();
+    for (a, b) in neighbours {
+        let (flips_a, flips_b) = (copy_flips(rules, a), copy_flips(rules, b));
+        // Flips as (across seam, along seam)
+        let ((across_a, along_a), (across_b, along_b)) = if horizontal {
+            (flips_a, flips_b)
+        } else {
+            ((flips_a.1, flips_a.0), (flips_b.1, flips_b.0))
+        };
+        let (last_a, last_b) = (!across_a, across_b);
+        let reversed = along_a != along_b;
+        if last_a == last_b && !reversed {
+            continue;
+        }
+        let seam = Seam {
+            last_a: last_a && last_b,
+            last_b: last_a || last_b,
+            reversed,
+        };
+        if !seams.contains(&seam) {
+            seams.push(seam);
+        }
+    }
+    seams
+}
+
+// Rules and copies are given as (horizontal, vertical) stacking
+pub fn blend_seams(
+    radii: &mut [f64],
+    width: usize,
+    height: usize,
+    band: usize,
+    blend: SeamBlend,
+    rules: (StackRule, StackRule),
+    copies: (usize, usize),
+) {
+    for horizontal in [true, false] {
+        let (lines, length) = if horizontal {
+            (width, height)
+        } else {
+            (height, width)
+        };
+        let index = |line: usize, position: usize| {
+            if horizontal {
+                position * width + line
+            } else {
+                line * width + position
+            }
+        };
+        let band = band.min(lines / 2);
+        let line = |last: bool, d: usize| if last { lines - 1 - d } else { d };
+        for seam in find_seams(rules, copies, horizontal) {
+            // Edge meeting its own reversal has each pair of positions once
+            let positions = if seam.last_a == seam.last_b {
+                length / 2
+            } else {
+                length
+            };
+            for position in 0..positions {
+                let other = if seam.reversed {
+                    length - 1 - position
+                } else {
+                    position
+                };
+                blend_band(
+                    radii,
+                    band,
+                    blend,
+                    |d| index(line(seam.last_a, d), position),
+                    |d| index(line(seam.last_b, d), other),
+                );
+            }
+        }
+    }
+}
+
+// Blends two bands of values adjacent to seam, index functions map distance from seam
+// to position in vector. Crossfade shifts both sides towards mean edge value,
+// feather mixes each side with its mirrored counterpart from the other side of seam.
+fn blend_band(
+    radii: &mut [f64],
+    band: usize,
+    blend: SeamBlend,
+    index_a: impl Fn(usize) -> usize,
+    index_b: impl Fn(usize) -> usize,
+) {
+    let (edge_a, edge_b) = (radii[index_a(0)], radii[index_b(0)]);
+    for d in 0..band {
+        let (value_a, value_b) = (radii[index_a(d)], radii[index_b(d)]);
+        let weight = 0.5 * (1.0 - { d as f64 } / { band as f64 });
+        let (target_a, target_b) = match blend {
+            SeamBlend::Crossfade => (value_a + edge_b - edge_a, value_b + edge_a - edge_b),
+            SeamBlend::Feather => (value_b, value_a),
+        };
+        radii[index_a(d)] = value_a + weight * (target_a - value_a);
+        radii[index_b(d)] = value_b + weight * (target_b - value_b);
+    }
+}
+
+// Seam is only reported when its edges differ more than any pair of neighbouring
+// columns (or rows) inside image does, so that seamless fine textures don't trip it
+fn horizontal_seam_mismatch(params: &Parameters) -> f64 {
+    let width = params.image_width as i32;
+    let rows = params.surface_height() as i32;
+    let column_pair = |i_a, i_b| rms_difference((0..rows).map(|j| (i_a, j, i_b, j)), params);
+    let interior = (1..width)
+        .map(|i| column_pair(i - 1, i))
+        .fold(0.0, f64::max);
+    (0..params.stack_horizontal as i32)
+        .map(|p| column_pair(p * width - 1, p * width) - interior)
+        .fold(0.0, f64::max)
+}
+
+fn vertical_seam_mismatch(params: &Parameters) -> f64 {
+    let height = params.image_height as i32;
+    let columns = params.surface_width() as i32;
+    let row_pair = |j_a, j_b| rms_difference((0..columns).map(|i| (i, j_a, i, j_b)), params);
+    let interior = (1..height).map(|j| row_pair(j - 1, j)).fold(0.0, f64::max);
+    (1..params.stack_vertical as i32)
+        .map(|q| row_pair(q * height - 1, q * height) - interior)
+        .fold(0.0, f64::max)
+}
+
+fn rms_difference(pairs: impl Iterator<Item = (i32, i32, i32, i32)>, params: &Parameters) -> f64 {
+    let (mut sum, mut count) = (0.0, 0usize);
+    for (i_a, j_a, i_b, j_b) in pairs {
+        let difference = params.get_rho_looped(i_a, j_a) - params.get_rho_looped(i_b, j_b);
+        sum += difference * difference;
+        count += 1;
+    }
+    (sum / { count.max(1) as f64 }).sqrt()
+}
+
+pub fn check_seams(params: &Parameters, threshold: f64) {
+    let horizontal = horizontal_seam_mismatch(params) / params.relief_depth;
+    if horizontal > threshold {
+        eprintln!(
+            "warning: Seam between left and right image edges differs by {:.0}% of relief depth",
+            horizontal * 100.0
+        );
+    }
+    let vertical = vertical_seam_mismatch(params) / params.relief_depth;
+    if vertical > threshold {
+        eprintln!(
+            "warning: Seam between top and bottom image edges differs by {:.0}% of relief depth",
+            vertical * 100.0
+        );
+    }
+}
+
+#[cfg(test)]
+const REPEAT: (StackRule, StackRule) = (StackRule::Repeat, StackRule::Repeat);
+
+#[test]
+fn test_blend_seams_matches_edges() {
+    for blend in [SeamBlend::Crossfade, SeamBlend::Feather] {
+        let mut radii = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        blend_seams(&mut radii, 3, 3, 1, blend, REPEAT, (1, 2));
+        for k in 0..3 {
+            assert_eq!(radii[3 * k], radii[3 * k + 2]);
+            assert_eq!(radii[k], radii[6 + k]);
+        }
+    }
+}
+
+#[test]
+fn test_blend_band_keeps_far_values() {
+    let mut radii = vec![0.0, 1.0, 1.0, 1.0, 1.0, 4.0];
+    blend_seams(&mut radii, 6, 1, 2, SeamBlend::Crossfade, REPEAT, (1, 1));
+    assert_eq!(radii, vec![2.0, 2.0, 1.0, 1.0, 0.0, 2.0]);
+}
+
+#[test]
+fn test_blend_seams_follows_stack_rules() {
+    // Mirrored pairs of copies meet at same edges, so there are no seams
+    let radii = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+    let mut blended = radii.clone();
+    let mirror = (StackRule::Mirror, StackRule::Repeat);
+    blend_seams(&mut blended, 3, 2, 1, SeamBlend::Crossfade, mirror, (2, 1));
+    assert_eq!(blended, radii);
+    // Rotated copies meet at each edge reversed
+    let rotate = (StackRule::Rotate, StackRule::Repeat);
+    blend_seams(&mut blended, 3, 2, 1, SeamBlend::Crossfade, rotate, (2, 1));
+    assert_eq!(blended, vec![1.5, 1.0, 3.5, 1.5, 4.0, 3.5]);
+}
+
+#[test]
+fn test_seam_mismatch_ignores_seamless_texture() {
+    use crate::parameters::test_cli_arguments_with_pixels;
+    use image::Luma;
+    let arguments = "img2roller -d 4 -e 1 --sv 2 test.png";
+    // Alternating columns and rows continue across seams
+    let texture = test_cli_arguments_with_pixels(arguments, (8, 4), |x, y| {
+        Luma([if (x + y) % 2 == 0 { 0 } else { u16::MAX }])
+    })
+    .unwrap();
+    assert_eq!(horizontal_seam_mismatch(&texture), 0.0);
+    assert_eq!(vertical_seam_mismatch(&texture), 0.0);
+    // Ramp jumps back at seam by 7 (3 vertically) of its steps, 1 of them is tolerated
+    let ramp =
+        test_cli_arguments_with_pixels(arguments, (8, 4), |x, y| Luma([{ 6000 * (x + y) } as u16]))
+            .unwrap();
+    let step = horizontal_seam_mismatch(&ramp) / 6.0;
+    assert!(step > 0.0);
+    assert!((vertical_seam_mismatch(&ramp) - 2.0 * step).abs() < 1e-9);
+}
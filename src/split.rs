@@ -1,21 +1,110 @@
 use crate::parameters::Parameters;
+use crate::vectors::{scalar_product, Vector3};
+use std::f64::consts::TAU;
 
+// Rule choosing diagonal of surface quad
+#[derive(Clone, Copy)]
+pub enum SplitStrategy {
+    // Diagonal along which radii are fitted best by line (over four vertices)
+    LeastSquares,
+    // Shorter diagonal in 3D
+    Shortest,
+    // Diagonal with smaller angle between normals of resulting triangles
+    MinDihedral,
+    // Diagonals alternate in checkerboard order regardless of relief, with odd number
+    // of vertex columns two columns of quads at image seam have the same diagonal
+    Alternating,
+    // Diagonal maximizing smallest angle of resulting triangles (Delaunay-like)
+    MaxMinAngle,
+}
+
+impl SplitStrategy {
+    pub fn from_name(name: Option<&String>) -> SplitStrategy {
+        match name.map(String::as_str) {
+            Some("shortest") => SplitStrategy::Shortest,
+            Some("dihedral") => SplitStrategy::MinDihedral,
+            Some("alternating") => SplitStrategy::Alternating,
+            Some("delaunay") => SplitStrategy::MaxMinAngle,
+            _ => SplitStrategy::LeastSquares,
+        }
+    }
+}
+
+// Whether quad is split along top-left to bottom-right diagonal, and radii of its vertices
 pub fn split_quad_optimal(params: &Parameters, i: usize, j: usize) -> (bool, f64, f64, f64, f64) {
     let (i, j) = (i as i32, j as i32);
-    let corner_tl = params.get_rho_looped(i - 1, j - 1);
-    let corner_tr = params.get_rho_looped(i - 1, j + 2);
-    let corner_bl = params.get_rho_looped(i + 2, j - 1);
-    let corner_br = params.get_rho_looped(i + 2, j + 2);
-    let quad_tl = params.get_rho_looped(i, j);
-    let quad_tr = params.get_rho_looped(i + 1, j);
-    let quad_bl = params.get_rho_looped(i, j + 1);
-    let quad_br = params.get_rho_looped(i + 1, j + 1);
-    let tlbr_split_score = lls_sse(corner_tl, quad_tl, quad_br, corner_br);
-    let trbl_split_score = lls_sse(corner_tr, quad_tr, quad_bl, corner_bl);
-    let tlbr_split = tlbr_split_score < trbl_split_score;
+    let quad_tl = params.get_vertex_rho_looped(i, j);
+    let quad_tr = params.get_vertex_rho_looped(i + 1, j);
+    let quad_bl = params.get_vertex_rho_looped(i, j + 1);
+    let quad_br = params.get_vertex_rho_looped(i + 1, j + 1);
+    let radii = [quad_tl, quad_tr, quad_bl, quad_br];
+    let tlbr_split = match params.split_strategy {
+        SplitStrategy::LeastSquares => {
+            let corner_tl = params.get_vertex_rho_looped(i - 1, j - 1);
+            let corner_tr = params.get_vertex_rho_looped(i - 1, j + 2);
+            let corner_bl = params.get_vertex_rho_looped(i + 2, j - 1);
+            let corner_br = params.get_vertex_rho_looped(i + 2, j + 2);
+            let tlbr_split_score = lls_sse(corner_tl, quad_tl, quad_br, corner_br);
+            let trbl_split_score = lls_sse(corner_tr, quad_tr, quad_bl, corner_bl);
+            tlbr_split_score < trbl_split_score
+        }
+        SplitStrategy::Shortest => {
+            let [tl, tr, bl, br] = quad_points(params, i, j, radii);
+            Vector3::from_points(&tl, &br).length() < Vector3::from_points(&tr, &bl).length()
+        }
+        SplitStrategy::MinDihedral => {
+            let [tl, tr, bl, br] = quad_points(params, i, j, radii);
+            let cos_tlbr = scalar_product(&normal(&tl, &br, &tr), &normal(&bl, &br, &tl));
+            let cos_trbl = scalar_product(&normal(&bl, &tr, &tl), &normal(&bl, &br, &tr));
+            cos_tlbr > cos_trbl
+        }
+        SplitStrategy::Alternating => (i + j) % 2 == 0,
+        SplitStrategy::MaxMinAngle => {
+            let [tl, tr, bl, br] = quad_points(params, i, j, radii);
+            let min_tlbr = min_angle(&tl, &br, &tr).min(min_angle(&bl, &br, &tl));
+            let min_trbl = min_angle(&bl, &tr, &tl).min(min_angle(&bl, &br, &tr));
+            min_tlbr > min_trbl
+        }
+    };
     (tlbr_split, quad_tl, quad_tr, quad_bl, quad_br)
 }
 
+// Vertices of quad (top-left, top-right, bottom-left, bottom-right) around roller axis
+fn quad_points(params: &Parameters, i: i32, j: i32, radii: [f64; 4]) -> [Vector3; 4] {
+    let phi_step = TAU / { params.surface_width() as f64 };
+    let z_step = params.roller_length / { (params.surface_height() - 1) as f64 };
+    let point = |di: i32, dj: i32, rho: f64| {
+        let phi = params.vertex_position({ i + di } as usize) * phi_step;
+        let z = -params.vertex_position({ j + dj } as usize) * z_step;
+        Vector3::new(rho * phi.cos(), rho * phi.sin(), z)
+    };
+    let [rho_tl, rho_tr, rho_bl, rho_br] = radii;
+    [
+        point(0, 0, rho_tl),
+        point(1, 0, rho_tr),
+        point(0, 1, rho_bl),
+        point(1, 1, rho_br),
+    ]
+}
+
+fn normal(vec_a: &Vector3, vec_b: &Vector3, vec_c: &Vector3) -> Vector3 {
+    let vec_ab = Vector3::from_points(vec_a, vec_b);
+    let vec_ac = Vector3::from_points(vec_a, vec_c);
+    Vector3::from_cross_product(vec_ab, vec_ac).normalize()
+}
+
+fn min_angle(vec_a: &Vector3, vec_b: &Vector3, vec_c: &Vector3) -> f64 {
+    let angle = |origin: &Vector3, end_1: &Vector3, end_2: &Vector3| {
+        let side_1 = Vector3::from_points(origin, end_1);
+        let side_2 = Vector3::from_points(origin, end_2);
+        let cos = scalar_product(&side_1, &side_2) / (side_1.length() * side_2.length());
+        cos.clamp(-1.0, 1.0).acos()
+    };
+    angle(vec_a, vec_b, vec_c)
+        .min(angle(vec_b, vec_c, vec_a))
+        .min(angle(vec_c, vec_a, vec_b))
+}
+
 fn lls_sse(y1: f64, y2: f64, y3: f64, y4: f64) -> f64 {
     let y_sum = y1 + y2 + y3 + y4;
     let xy_sum = y2 + 2.0 * y3 + 3.0 * y4;
@@ -38,3 +127,33 @@ fn test_lls_sse_exact_linear() {
 fn test_lls_sse_compare() {
     assert!(lls_sse(1.0, 2.0, 3.0, 5.0) < lls_sse(1.0, 2.0, 3.0, 5.1))
 }
+
+#[test]
+fn test_split_strategies() {
+    use crate::parameters::test_cli_arguments_with_pixels;
+    use image::Luma;
+    // Single raised pixel in flat area, it is bottom-right corner of quad (0, 0)
+    // and top-right corner of quad (0, 1)
+    let split = |strategy: &str, i: usize, j: usize| {
+        let arguments = format!("img2roller -d 2 -e 0.2 --quad-split {} test.png", strategy);
+        let parameters = test_cli_arguments_with_pixels(&arguments, (8, 8), |x, y| {
+            Luma([if (x, y) == (1, 1) { u16::MAX } else { 0 }])
+        })
+        .unwrap();
+        split_quad_optimal(&parameters, i, j).0
+    };
+    // Flat diagonals continue along neighbouring quads
+    assert!(!split("lls", 0, 0));
+    assert!(split("lls", 0, 1));
+    // Diagonal through raised corner is longer and makes narrower triangles
+    assert!(!split("shortest", 0, 0));
+    assert!(split("shortest", 0, 1));
+    assert!(!split("delaunay", 0, 0));
+    assert!(split("delaunay", 0, 1));
+    // Diagonal avoids raised corner, so that one of triangles stays flat
+    assert!(!split("dihedral", 0, 0));
+    assert!(split("dihedral", 0, 1));
+    assert!(!split("dihedral", 1, 1));
+    assert!(split("alternating", 0, 0));
+    assert!(!split("alternating", 1, 0));
+}
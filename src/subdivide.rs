@@ -0,0 +1,105 @@
+use crate::parameters::Parameters;
+
+// Vertex grid refined by given factor between pixel centers, radii are interpolated
+// bicubically, wrapping around circumference and clamped at roller ends
+pub struct Subdivision {
+    pub factor: usize,
+    pub columns: usize,
+    pub radii: Vec<f64>,
+}
+
+impl Subdivision {
+    // Vertex grid as (columns, rows), None if it does not fit in memory addressing
+    pub fn grid_size(width: usize, height: usize, factor: usize) -> Option<(usize, usize)> {
+        let columns = width.checked_mul(factor)?;
+        let rows = (height - 1).checked_mul(factor)?.checked_add(1)?;
+        Some((columns, rows))
+    }
+
+    // Grid size should be checked beforehand
+    pub fn new(params: &Parameters, factor: usize) -> Subdivision {
+        let (width, height) = (params.surface_width(), params.surface_height());
+        let (columns, rows) = Subdivision::grid_size(width, height, factor).unwrap();
+        let (bottom_radius, surface_radius) = (params.bottom_radius(), params.surface_radius());
+        let last_row = height as i32 - 1;
+        let mut radii = Vec::with_capacity(columns * rows);
+        for j in 0..rows {
+            let (row, weights_y) = split_position(j, factor);
+            for i in 0..columns {
+                let (column, weights_x) = split_position(i, factor);
+                let mut rho = 0.0;
+                for (dj, weight_y) in (-1..=2).zip(weights_y) {
+                    let j_px = (row + dj).clamp(0, last_row);
+                    for (di, weight_x) in (-1..=2).zip(weights_x) {
+                        rho += weight_y * weight_x * params.get_rho_looped(column + di, j_px);
+                    }
+                }
+                // Overshoot of interpolation should not leave relief depth range
+                radii.push(rho.clamp(bottom_radius, surface_radius));
+            }
+        }
+        Subdivision {
+            factor,
+            columns,
+            radii,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.radii.len() / self.columns
+    }
+
+    pub fn get_rho(&self, i: usize, j: usize) -> f64 {
+        self.radii[j * self.columns + i]
+    }
+}
+
+// Pixel preceding vertex and weights of four surrounding pixels
+fn split_position(n: usize, factor: usize) -> (i32, [f64; 4]) {
+    let t = { (n % factor) as f64 } / { factor as f64 };
+    ((n / factor) as i32, catmull_rom_weights(t))
+}
+
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[cfg(test)]
+#[test]
+fn test_catmull_rom_weights() {
+    assert_eq!(catmull_rom_weights(0.0), [0.0, 1.0, 0.0, 0.0]);
+    assert_eq!(catmull_rom_weights(0.5), [-0.0625, 0.5625, 0.5625, -0.0625]);
+    let sum: f64 = catmull_rom_weights(0.3).iter().sum();
+    assert!((sum - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_subdivision_wraps_and_clamps() {
+    use crate::parameters::test_cli_arguments_with_pixels;
+    use image::Luma;
+    // Columns form smooth periodic profile around circumference
+    let arguments = "img2roller -d 2 -e 0.5 --subdivide 2 test.png";
+    let parameters = test_cli_arguments_with_pixels(arguments, (4, 3), |x, _| {
+        Luma([[0, u16::MAX / 2, u16::MAX, u16::MAX / 2][x as usize]])
+    })
+    .unwrap();
+    let subdivision = parameters.subdivision.as_ref().unwrap();
+    // Between last and first column pixels, using pixels 2, 3, 0, 1
+    assert!((subdivision.get_rho(7, 2) - 0.59375).abs() < 1e-4);
+    // Rows outside of image repeat end rows
+    let parameters = test_cli_arguments_with_pixels(arguments, (4, 3), |_, y| {
+        Luma([if y == 0 { u16::MAX } else { 0 }])
+    })
+    .unwrap();
+    let subdivision = parameters.subdivision.as_ref().unwrap();
+    assert_eq!(subdivision.rows(), 5);
+    assert_eq!(subdivision.get_rho(0, 1), 0.75);
+    assert_eq!(subdivision.get_rho(0, 4), 0.5);
+    assert_eq!(Subdivision::grid_size(60, 40, usize::MAX), None);
+}
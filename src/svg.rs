@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::fs::read;
+
+pub struct SvgImage {
+    tree: usvg::Tree,
+}
+
+impl SvgImage {
+    pub fn from_file(filename: &str) -> Result<SvgImage> {
+        let data = read(filename).with_context(|| format!("Failed to read file '{}'", filename))?;
+        let options = usvg::Options::default();
+        let tree = usvg::Tree::from_data(&data, &options.to_ref())
+            .with_context(|| format!("Failed to decode file '{}'", filename))?;
+        Ok(SvgImage { tree })
+    }
+
+    // Size in SVG user units, used as pixels when no grid step is given
+    pub fn dimensions(&self) -> (u32, u32) {
+        let size = self.tree.svg_node().size;
+        (
+            { size.width().round() as u32 }.max(1),
+            { size.height().round() as u32 }.max(1),
+        )
+    }
+
+    // Artwork is stretched to fill exactly given number of pixels,
+    // areas without fill stay transparent
+    pub fn render(&self, width: u32, height: u32) -> Result<DynamicImage> {
+        let size = self.tree.svg_node().size;
+        let mut pixmap = tiny_skia::Pixmap::new(width, height).with_context(|| {
+            format!("Failed to render SVG image at {}x{} pixels", width, height)
+        })?;
+        let scale_x = { width as f64 } / size.width();
+        let scale_y = { height as f64 } / size.height();
+        let transform = tiny_skia::Transform::from_scale(scale_x as f32, scale_y as f32);
+        resvg::render(
+            &self.tree,
+            usvg::FitTo::Original,
+            transform,
+            pixmap.as_mut(),
+        );
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            let color = pixmap.pixel(x, y).unwrap().demultiply();
+            Rgba([color.red(), color.green(), color.blue(), color.alpha()])
+        });
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_svg_render() {
+    let data = concat!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2">"#,
+        r#"<rect x="0" y="0" width="2" height="2" fill="red"/></svg>"#
+    );
+    let options = usvg::Options::default();
+    let svg = SvgImage {
+        tree: usvg::Tree::from_str(data, &options.to_ref()).unwrap(),
+    };
+    assert_eq!(svg.dimensions(), (4, 2));
+    let image = svg.render(8, 2).unwrap().into_rgba8();
+    assert!(svg.render(8, 0).is_err());
+    assert_eq!(image.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(3, 0), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(4, 0)[3], 0);
+}
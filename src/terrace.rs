@@ -0,0 +1,66 @@
+use anyhow::{ensure, Result};
+
+#[derive(Clone, Copy)]
+pub enum Terracing {
+    Levels(u32),
+    LayerHeight(f64),
+}
+
+// Relief levels counted from surface (including it) with constant step between them
+pub struct Terraces {
+    pub levels: u32,
+    pub step: f64,
+}
+
+impl Terraces {
+    pub fn new(terracing: Terracing, relief_depth: f64) -> Result<Terraces> {
+        match terracing {
+            Terracing::Levels(levels) => Ok(Terraces {
+                levels,
+                step: relief_depth / { (levels - 1) as f64 },
+            }),
+            Terracing::LayerHeight(step) => {
+                ensure!(
+                    step > 0.0 && step <= relief_depth,
+                    "Layer height ({}) should be greater than zero and not exceed relief depth ({})",
+                    step,
+                    relief_depth
+                );
+                // Small tolerance keeps last level when depth is multiple of layer height
+                let levels = (relief_depth / step + 1e-9).floor() as u32 + 1;
+                Ok(Terraces { levels, step })
+            }
+        }
+    }
+
+    pub fn apply(&self, radii: &mut [f64], surface_radius: f64) {
+        let deepest_level = { (self.levels - 1) as f64 };
+        for rho in radii.iter_mut() {
+            let level = ((surface_radius - *rho) / self.step)
+                .round()
+                .clamp(0.0, deepest_level);
+            *rho = surface_radius - level * self.step;
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_terraces_levels() {
+    let terraces = Terraces::new(Terracing::Levels(3), 1.0).unwrap();
+    let mut radii = vec![1.0, 1.2, 1.3, 1.76, 2.0];
+    terraces.apply(&mut radii, 2.0);
+    assert_eq!(radii, vec![1.0, 1.0, 1.5, 2.0, 2.0]);
+}
+
+#[test]
+fn test_terraces_layer_height() {
+    let terraces = Terraces::new(Terracing::LayerHeight(0.4), 1.0).unwrap();
+    assert_eq!(terraces.levels, 3);
+    let mut radii = vec![1.0, 1.5];
+    terraces.apply(&mut radii, 2.0);
+    assert_eq!(radii, vec![1.2, 1.6]);
+    let terraces = Terraces::new(Terracing::LayerHeight(0.2), 1.0).unwrap();
+    assert_eq!(terraces.levels, 6);
+    assert!(Terraces::new(Terracing::LayerHeight(1.5), 1.0).is_err());
+}
@@ -0,0 +1,148 @@
+use ab_glyph::{point, Font, FontVec, Glyph, PxScale, ScaleFont};
+use anyhow::{ensure, Context, Result};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::fs::read;
+
+// Line height in pixels when no grid step is given
+const NOMINAL_HEIGHT: f32 = 64.0;
+
+// Single line of text with half of space width margin at both sides,
+// so that copies stacked horizontally are separated by space
+pub struct TextImage {
+    font: FontVec,
+    text: String,
+}
+
+impl TextImage {
+    pub fn new(text: &str, font_filename: &str) -> Result<TextImage> {
+        ensure!(
+            !text.trim().is_empty(),
+            "Text should contain at least one visible character"
+        );
+        let data = read(font_filename)
+            .with_context(|| format!("Failed to read file '{}'", font_filename))?;
+        let font = FontVec::try_from_vec(data)
+            .with_context(|| format!("Failed to decode font '{}'", font_filename))?;
+        // Missing characters are rendered as glyph 0, usually empty or a box
+        if let Some(missing) =
+            (text.chars()).find(|c| !c.is_whitespace() && font.glyph_id(*c).0 == 0)
+        {
+            anyhow::bail!(
+                "Font '{}' has no glyph for character '{}'",
+                font_filename,
+                missing
+            );
+        }
+        ensure!(
+            text.chars()
+                .any(|c| font.outline(font.glyph_id(c)).is_some()),
+            "Font '{}' has no outlines for given text",
+            font_filename
+        );
+        Ok(TextImage {
+            font,
+            text: String::from(text),
+        })
+    }
+
+    // Glyphs positioned on baseline and total width including margins
+    fn layout(&self, scale: PxScale) -> (Vec<Glyph>, f32) {
+        let scaled_font = self.font.as_scaled(scale);
+        let margin = 0.5 * scaled_font.h_advance(scaled_font.glyph_id(' '));
+        let mut caret = point(margin, scaled_font.ascent());
+        let mut previous = None;
+        let mut glyphs = Vec::with_capacity(self.text.len());
+        for c in self.text.chars() {
+            let mut glyph = scaled_font.scaled_glyph(c);
+            if let Some(previous) = previous {
+                caret.x += scaled_font.kern(previous, glyph.id);
+            }
+            glyph.position = caret;
+            caret.x += scaled_font.h_advance(glyph.id);
+            previous = Some(glyph.id);
+            glyphs.push(glyph);
+        }
+        (glyphs, caret.x + margin)
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        let (_, width) = self.layout(PxScale::from(NOMINAL_HEIGHT));
+        ({ width.ceil() as u32 }.max(1), NOMINAL_HEIGHT as u32)
+    }
+
+    // Text is stretched to fill exactly given number of pixels,
+    // glyph coverage is stored in alpha channel
+    pub fn render(&self, width: u32, height: u32) -> DynamicImage {
+        let (_, nominal_width) = self.layout(PxScale::from(NOMINAL_HEIGHT));
+        let scale = PxScale {
+            x: NOMINAL_HEIGHT * { width as f32 } / nominal_width,
+            y: height as f32,
+        };
+        let (glyphs, _) = self.layout(scale);
+        let mut image = RgbaImage::new(width, height);
+        for glyph in glyphs {
+            if let Some(outlined) = self.font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, coverage| {
+                    let x = x as i32 + bounds.min.x as i32;
+                    let y = y as i32 + bounds.min.y as i32;
+                    if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                        return;
+                    }
+                    let pixel = image.get_pixel_mut(x as u32, y as u32);
+                    let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    *pixel = Rgba([0, 0, 0, pixel[3].max(alpha)]);
+                });
+            }
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_text_errors() {
+    let error = |text: &str, font_filename: &str| {
+        format!("{:#}", TextImage::new(text, font_filename).err().unwrap())
+    };
+    assert!(error(" \t", "missing.ttf").contains("visible character"));
+    assert!(error("A", "missing.ttf").contains("Failed to read file 'missing.ttf'"));
+    let font_filename = std::env::temp_dir()
+        .join(format!("img2roller_font_{}.ttf", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    std::fs::write(&font_filename, b"not a font").unwrap();
+    let message = error("A", &font_filename);
+    std::fs::remove_file(&font_filename).unwrap();
+    assert!(message.contains("Failed to decode font"));
+    let font_filename = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/square.ttf");
+    assert!(error("HELLO", font_filename).contains("no glyph for character 'H'"));
+    assert!(TextImage::new("A A", font_filename).is_ok());
+}
+
+#[test]
+fn test_text_rendering() {
+    // Fixture font has 1000 units in line height, space advance of 500 units and square
+    // glyph 'A' from 100 to 500 units with advance of 600 units, so 16 pixel margins at 64 pixels
+    let font_filename = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/square.ttf");
+    let text = TextImage::new("AA", font_filename).unwrap();
+    let (width, height) = text.dimensions();
+    assert_eq!((width, height), (109, 64));
+    let image = text.render(2 * width, height).to_rgba8();
+    let alpha = |x: u32, y: u32| image.get_pixel(x, y)[3];
+    let columns_alpha = |columns: std::ops::Range<u32>| {
+        columns
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .map(|(x, y)| alpha(x, y))
+            .max()
+            .unwrap()
+    };
+    // Margins of half space width stay transparent
+    assert_eq!(columns_alpha(0..2 * 22), 0);
+    assert_eq!(columns_alpha(2 * width - 2 * 22..2 * width), 0);
+    // Glyph squares are stretched with image, gap between them is transparent
+    assert_eq!(alpha(2 * 35, 40), 255);
+    assert_eq!(alpha(2 * 73, 40), 255);
+    assert_eq!(columns_alpha(2 * 50..2 * 58), 0);
+    assert_eq!(alpha(2 * 35, 5), 0);
+}
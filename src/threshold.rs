@@ -0,0 +1,127 @@
+use crate::plane::{Plane, Stacking};
+
+#[derive(Clone, Copy)]
+pub enum Threshold {
+    Fixed(f64),
+    Otsu,
+    Adaptive(usize),
+}
+
+// Heights are normalized (from 0 to 1) and quantized to these two levels,
+// adaptive threshold takes neighbouring copies into account
+pub fn apply_threshold(
+    heights: &mut [f64],
+    width: usize,
+    stacking: Stacking,
+    threshold: Threshold,
+) {
+    match threshold {
+        Threshold::Fixed(level) => quantize(heights, |_| level),
+        Threshold::Otsu => {
+            let level = otsu_level(heights);
+            quantize(heights, |_| level)
+        }
+        Threshold::Adaptive(radius) => {
+            let plane = Plane {
+                width,
+                height: heights.len() / width,
+                stacking,
+                values: heights.to_vec(),
+            };
+            let local_means = plane.box_mean(radius);
+            quantize(heights, |n| local_means.values[n])
+        }
+    }
+}
+
+fn quantize(heights: &mut [f64], level: impl Fn(usize) -> f64) {
+    for (n, height) in heights.iter_mut().enumerate() {
+        *height = if *height > level(n) { 1.0 } else { 0.0 };
+    }
+}
+
+fn otsu_level(heights: &[f64]) -> f64 {
+    const BINS: usize = 256;
+    let mut histogram = [0usize; BINS];
+    for height in heights {
+        histogram[{ (height * { (BINS - 1) as f64 }).round() as usize }.min(BINS - 1)] += 1;
+    }
+    let total = heights.len() as f64;
+    let total_sum: f64 = (0..BINS).map(|k| { k * histogram[k] } as f64).sum();
+    let (mut weight_low, mut sum_low) = (0.0, 0.0);
+    let (mut best_variance, mut best_bin) = (-1.0, 0);
+    for (k, count) in histogram.iter().enumerate() {
+        weight_low += *count as f64;
+        sum_low += { k * count } as f64;
+        let weight_high = total - weight_low;
+        if weight_low == 0.0 || weight_high == 0.0 {
+            continue;
+        }
+        let mean_difference = sum_low / weight_low - (total_sum - sum_low) / weight_high;
+        let variance = weight_low * weight_high * mean_difference * mean_difference;
+        if variance > best_variance {
+            (best_variance, best_bin) = (variance, k);
+        }
+    }
+    ({ best_bin as f64 } + 0.5) / { (BINS - 1) as f64 }
+}
+
+impl Plane {
+    // Mean over square window, computed as horizontal and vertical pass
+    fn box_mean(&self, radius: usize) -> Plane {
+        let radius = radius as isize;
+        let window = { (2 * radius + 1) as f64 };
+        let rows_mean = self.map_pixels(|i, j| {
+            (-radius..=radius).map(|k| self.get(i + k, j)).sum::<f64>() / window
+        });
+        rows_mean.map_pixels(|i, j| {
+            (-radius..=radius)
+                .map(|k| rows_mean.get(i, j + k))
+                .sum::<f64>()
+                / window
+        })
+    }
+}
+
+#[cfg(test)]
+use crate::parameters::StackRule;
+
+#[test]
+fn test_otsu_level() {
+    let level = otsu_level(&[0.1, 0.12, 0.15, 0.8, 0.85, 0.9]);
+    assert!(0.15 < level && level < 0.8);
+}
+
+#[test]
+fn test_box_mean() {
+    let plane = |width: usize, values: Vec<f64>, rules: (StackRule, StackRule), copies| Plane {
+        width,
+        height: values.len() / width,
+        stacking: Stacking { rules, copies },
+        values,
+    };
+    let repeat = (StackRule::Repeat, StackRule::Repeat);
+    let mut values = vec![0.0; 9];
+    values[4] = 9.0;
+    let means = plane(3, values, repeat, (1, 2)).box_mean(1);
+    assert_eq!(means.values, vec![1.0; 9]);
+    let means = plane(4, vec![3.0, 0.0, 0.0, 0.0], repeat, (1, 1)).box_mean(1);
+    assert_eq!(means.values, vec![1.0, 1.0, 0.0, 1.0]);
+    // Mirrored neighbour copy repeats edge column
+    let mirror = (StackRule::Mirror, StackRule::Repeat);
+    let means = plane(4, vec![3.0, 0.0, 0.0, 0.0], mirror, (2, 1)).box_mean(1);
+    assert_eq!(means.values, vec![2.0, 1.0, 0.0, 0.0]);
+    // Without vertical stacking bottom row is not wrapped to top
+    let means = plane(1, vec![3.0, 0.0, 0.0], repeat, (1, 1)).box_mean(1);
+    assert_eq!(means.values, vec![2.0, 1.0, 0.0]);
+}
+
+#[test]
+fn test_global_threshold() {
+    let mut heights = vec![0.0, 0.25, 0.5, 0.75];
+    apply_threshold(&mut heights, 4, Stacking::SURFACE, Threshold::Fixed(0.4));
+    assert_eq!(heights, vec![0.0, 0.0, 1.0, 1.0]);
+    let mut heights = vec![0.1, 0.8, 0.15, 0.9, 0.12, 0.85];
+    apply_threshold(&mut heights, 3, Stacking::SURFACE, Threshold::Otsu);
+    assert_eq!(heights, vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+}
@@ -0,0 +1,118 @@
+use anyhow::{ensure, Context, Result};
+use std::fs::read_to_string;
+
+// Applied to normalized heights (0 is full depth, 1 is surface) in order:
+// curve points, gamma, contrast, output levels
+#[derive(PartialEq)]
+pub struct ToneCurve {
+    pub curve_points: Vec<(f64, f64)>,
+    pub gamma: f64,
+    pub contrast: f64,
+    pub output_levels: (f64, f64),
+}
+
+impl ToneCurve {
+    pub const IDENTITY: ToneCurve = ToneCurve {
+        curve_points: Vec::new(),
+        gamma: 1.0,
+        contrast: 1.0,
+        output_levels: (0.0, 1.0),
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == ToneCurve::IDENTITY
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        let value = interpolate_points(&self.curve_points, value);
+        let value = value.powf(self.gamma.recip());
+        let value = (0.5 + (value - 0.5) * self.contrast).clamp(0.0, 1.0);
+        let (output_min, output_max) = self.output_levels;
+        output_min + value * (output_max - output_min)
+    }
+}
+
+fn interpolate_points(points: &[(f64, f64)], value: f64) -> f64 {
+    if points.is_empty() {
+        return value;
+    }
+    let n = points.partition_point(|(x, _)| *x < value);
+    if n == 0 {
+        points[0].1
+    } else if n == points.len() {
+        points[n - 1].1
+    } else {
+        let (x_left, y_left) = points[n - 1];
+        let (x_right, y_right) = points[n];
+        y_left + (value - x_left) * (y_right - y_left) / (x_right - x_left)
+    }
+}
+
+pub fn check_curve_points(points: &[(f64, f64)]) -> Result<(), String> {
+    if points.len() < 2 {
+        return Err(String::from("at least two curve points are required"));
+    }
+    if points
+        .iter()
+        .any(|(x, y)| !(0.0..=1.0).contains(x) || !(0.0..=1.0).contains(y))
+    {
+        return Err(String::from("curve points should be between 0 and 1"));
+    }
+    if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+        return Err(String::from("curve points should be sorted by input value"));
+    }
+    Ok(())
+}
+
+// Lookup table is list of output values (from 0 to 1) for evenly spaced input values,
+// separated by whitespace or commas, lines starting with '#' are ignored
+pub fn read_lut_file(filename: &str) -> Result<Vec<(f64, f64)>> {
+    let text =
+        read_to_string(filename).with_context(|| format!("Failed to read file '{}'", filename))?;
+    let values = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .with_context(|| format!("Failed to parse lookup table '{}'", filename))?;
+    ensure!(
+        values.len() >= 2,
+        "Lookup table '{}' should contain at least two values",
+        filename
+    );
+    let last = { values.len() - 1 } as f64;
+    let points: Vec<(f64, f64)> = values
+        .into_iter()
+        .enumerate()
+        .map(|(n, value)| ({ n as f64 } / last, value))
+        .collect();
+    check_curve_points(&points).map_err(anyhow::Error::msg)?;
+    Ok(points)
+}
+
+#[cfg(test)]
+#[test]
+fn test_tone_curve_identity() {
+    for value in [0.0, 0.3, 1.0] {
+        assert_eq!(ToneCurve::IDENTITY.apply(value), value);
+    }
+}
+
+#[test]
+fn test_tone_curve_points() {
+    let curve = ToneCurve {
+        curve_points: vec![(0.0, 0.0), (0.5, 0.8), (1.0, 1.0)],
+        ..ToneCurve::IDENTITY
+    };
+    assert_eq!(curve.apply(0.25), 0.4);
+    assert_eq!(curve.apply(0.75), 0.9);
+    let curve = ToneCurve {
+        contrast: 2.0,
+        output_levels: (0.5, 1.0),
+        ..ToneCurve::IDENTITY
+    };
+    assert_eq!(curve.apply(0.125), 0.5);
+    assert_eq!(curve.apply(0.5), 0.75);
+}
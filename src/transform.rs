@@ -0,0 +1,240 @@
+use crate::image::is_float_image;
+use anyhow::{ensure, Result};
+use image::imageops::replace;
+use image::{DynamicImage, ImageBuffer, Rgba, Rgba32FImage};
+
+// Rectangle in input image pixels
+#[derive(Clone, Copy)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl Flip {
+    pub fn from_name(name: Option<&String>) -> Option<Flip> {
+        match name.map(String::as_str) {
+            Some("horizontal") => Some(Flip::Horizontal),
+            Some("vertical") => Some(Flip::Vertical),
+            Some("both") => Some(Flip::Both),
+            _ => None,
+        }
+    }
+}
+
+// Margins in mm around image copy
+#[derive(Clone, Copy)]
+pub struct Padding {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl Padding {
+    pub fn horizontal(&self) -> f64 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> f64 {
+        self.top + self.bottom
+    }
+
+    // Margins in pixels as (top, right, bottom, left)
+    pub fn to_pixels(self, pixel_size: f64) -> [u32; 4] {
+        [self.top, self.right, self.bottom, self.left].map(|mm| (mm / pixel_size).round() as u32)
+    }
+}
+
+// Crop is applied first, then image is rotated clockwise and flipped
+pub fn orient_image(
+    image: DynamicImage,
+    crop: Option<Crop>,
+    rotation: Option<f64>,
+    flip: Option<Flip>,
+) -> Result<DynamicImage> {
+    let image = match crop {
+        Some(Crop {
+            x,
+            y,
+            width,
+            height,
+        }) => {
+            ensure!(
+                x.checked_add(width)
+                    .map_or(false, |end| end <= image.width())
+                    && y.checked_add(height)
+                        .map_or(false, |end| end <= image.height()),
+                "Crop rectangle {}x{} at ({}, {}) does not fit into {}x{} image",
+                width,
+                height,
+                x,
+                y,
+                image.width(),
+                image.height()
+            );
+            image.crop_imm(x, y, width, height)
+        }
+        None => image,
+    };
+    let angle = rotation.unwrap_or(0.0).rem_euclid(360.0);
+    let image = if angle % 90.0 != 0.0 {
+        rotate_image(image, angle)
+    } else {
+        match (angle / 90.0) as u32 {
+            1 => image.rotate90(),
+            2 => image.rotate180(),
+            3 => image.rotate270(),
+            _ => image,
+        }
+    };
+    Ok(match flip {
+        None => image,
+        Some(Flip::Horizontal) => image.fliph(),
+        Some(Flip::Vertical) => image.flipv(),
+        Some(Flip::Both) => image.rotate180(),
+    })
+}
+
+// Canvas is enlarged to fit rotated image, corners are left transparent.
+// Bilinear interpolation is done with premultiplied alpha, so that
+// transparent corners do not darken image edges.
+pub fn rotate_image(image: DynamicImage, angle: f64) -> DynamicImage {
+    let float = is_float_image(&image);
+    let source = image.into_rgba32f();
+    let (width, height) = ({ source.width() as f64 }, { source.height() as f64 });
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let new_width = (width * cos.abs() + height * sin.abs()).round().max(1.0);
+    let new_height = (width * sin.abs() + height * cos.abs()).round().max(1.0);
+    let sample = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= source.width() as i64 || y >= source.height() as i64 {
+            [0.0f64; 4]
+        } else {
+            let pixel = source.get_pixel(x as u32, y as u32).0;
+            let alpha = pixel[3] as f64;
+            [
+                pixel[0] as f64 * alpha,
+                pixel[1] as f64 * alpha,
+                pixel[2] as f64 * alpha,
+                alpha,
+            ]
+        }
+    };
+    let rotated = Rgba32FImage::from_fn(new_width as u32, new_height as u32, |i, j| {
+        // Inverse rotation of pixel center around image center
+        let dx = { i as f64 } + 0.5 - 0.5 * new_width;
+        let dy = { j as f64 } + 0.5 - 0.5 * new_height;
+        let x = dx * cos + dy * sin + 0.5 * width - 0.5;
+        let y = -dx * sin + dy * cos + 0.5 * height - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut premultiplied = [0.0f64; 4];
+        for (di, dj, weight) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            for (sum, value) in premultiplied.iter_mut().zip(sample(x0 + di, y0 + dj)) {
+                *sum += weight * value;
+            }
+        }
+        let alpha = premultiplied[3];
+        if alpha <= 0.0 {
+            Rgba([0.0; 4])
+        } else {
+            Rgba([
+                (premultiplied[0] / alpha) as f32,
+                (premultiplied[1] / alpha) as f32,
+                (premultiplied[2] / alpha) as f32,
+                alpha as f32,
+            ])
+        }
+    });
+    keep_precision(DynamicImage::ImageRgba32F(rotated), float)
+}
+
+// Margins are transparent, pixel counts are (top, right, bottom, left)
+pub fn pad_image(image: DynamicImage, [top, right, bottom, left]: [u32; 4]) -> DynamicImage {
+    if top + right + bottom + left == 0 {
+        return image;
+    }
+    let float = is_float_image(&image);
+    let (width, height) = (image.width() + left + right, image.height() + top + bottom);
+    let mut canvas = ImageBuffer::from_pixel(width, height, Rgba([0.0f32; 4]));
+    replace(&mut canvas, &image.into_rgba32f(), left as i64, top as i64);
+    keep_precision(DynamicImage::ImageRgba32F(canvas), float)
+}
+
+// Integer images stay integer, so that their values are not treated as raw float data
+pub fn keep_precision(image: DynamicImage, float: bool) -> DynamicImage {
+    if float {
+        image
+    } else {
+        DynamicImage::ImageRgba16(image.into_rgba16())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_orient_image() {
+    let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(4, 2, |x, y| {
+        image::Luma([{ 10 * x + 100 * y } as u8])
+    }));
+    let crop = Crop {
+        x: 1,
+        y: 0,
+        width: 3,
+        height: 2,
+    };
+    let oriented = orient_image(
+        image.clone(),
+        Some(crop),
+        Some(-270.0),
+        Some(Flip::Vertical),
+    );
+    let oriented = oriented.unwrap().into_luma8();
+    assert_eq!(oriented.dimensions(), (2, 3));
+    assert_eq!(oriented.get_pixel(0, 0)[0], 130);
+    assert_eq!(oriented.get_pixel(1, 2)[0], 10);
+    let crop = Crop { x: 2, ..crop };
+    assert!(orient_image(image.clone(), Some(crop), None, None).is_err());
+    let crop = Crop {
+        x: u32::MAX,
+        y: 0,
+        width: 10,
+        height: 1,
+    };
+    assert!(orient_image(image.clone(), Some(crop), None, None).is_err());
+    let crop = Crop {
+        x: 0,
+        y: 1,
+        width: 1,
+        height: u32::MAX,
+    };
+    assert!(orient_image(image, Some(crop), None, None).is_err());
+}
+
+#[test]
+fn test_rotate_and_pad() {
+    let image = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(10, 10, image::Luma([1000])));
+    let rotated = rotate_image(image.clone(), 45.0).into_rgba16();
+    assert_eq!(rotated.dimensions(), (14, 14));
+    assert_eq!(rotated.get_pixel(0, 0)[3], 0);
+    assert_eq!(rotated.get_pixel(7, 7), &Rgba([1000, 1000, 1000, u16::MAX]));
+    assert_eq!(rotated.get_pixel(7, 1)[0], 1000);
+    let padded = pad_image(image, [1, 0, 2, 3]).into_rgba16();
+    assert_eq!(padded.dimensions(), (13, 13));
+    assert_eq!(padded.get_pixel(2, 5)[3], 0);
+    assert_eq!(padded.get_pixel(3, 1)[3], u16::MAX);
+    assert_eq!(padded.get_pixel(3, 11)[3], 0);
+}
@@ -0,0 +1,525 @@
+use anyhow::{ensure, Context, Result};
+use std::collections::HashMap;
+use std::fs::read;
+
+// Vertices closer than this distance (in mm) are welded together
+const WELD_TOLERANCE: f64 = 1e-4;
+// Triangles with longest edge this many times longer than height are slivers
+const MAX_ASPECT_RATIO: f64 = 1e4;
+
+type Point = [f64; 3];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProblemKind {
+    // Edge used by single triangle
+    OpenEdge,
+    // Edge shared by more than two triangles
+    NonManifoldEdge,
+    // Edge shared by two triangles traversing it in same direction
+    FlippedEdge,
+    // Triangle with welded vertices or with height below weld tolerance
+    Degenerate,
+    SelfIntersection,
+}
+
+impl ProblemKind {
+    fn description(&self) -> &'static str {
+        match self {
+            ProblemKind::OpenEdge => "open edges",
+            ProblemKind::NonManifoldEdge => "non-manifold edges",
+            ProblemKind::FlippedEdge => "edges between inconsistently oriented triangles",
+            ProblemKind::Degenerate => "degenerate triangles",
+            ProblemKind::SelfIntersection => "self-intersections",
+        }
+    }
+}
+
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub position: Point,
+}
+
+// Triangle mesh with welded vertices
+pub struct Mesh {
+    pub vertices: Vec<Point>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    pub fn from_triangles(triangles: &[[Point; 3]]) -> Mesh {
+        let mut welder = Welder::default();
+        let triangles = triangles
+            .iter()
+            .map(|triangle| triangle.map(|point| welder.index(point)))
+            .collect();
+        Mesh {
+            vertices: welder.vertices,
+            triangles,
+        }
+    }
+
+    // Both binary and ASCII STL files are accepted
+    pub fn from_stl_file(filename: &str) -> Result<Mesh> {
+        let data = read(filename).with_context(|| format!("Failed to read file '{}'", filename))?;
+        let triangles = match binary_triangles(&data) {
+            Some(triangles) => triangles,
+            None => ascii_triangles(&data)
+                .with_context(|| format!("Failed to parse STL file '{}'", filename))?,
+        };
+        Ok(Mesh::from_triangles(&triangles))
+    }
+
+    pub fn find_problems(&self) -> Vec<Problem> {
+        let mut problems = self.edge_problems();
+        problems.extend(self.degenerate_triangles());
+        problems.extend(self.self_intersections());
+        problems
+    }
+
+    fn points(&self, triangle: &[usize; 3]) -> [Point; 3] {
+        triangle.map(|n| self.vertices[n])
+    }
+
+    // Closed oriented surface has every edge traversed once in each direction
+    fn edge_problems(&self) -> Vec<Problem> {
+        let mut directed: HashMap<(usize, usize), usize> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            if a == b || b == c || c == a {
+                continue;
+            }
+            for edge in [(a, b), (b, c), (c, a)] {
+                *directed.entry(edge).or_insert(0) += 1;
+            }
+        }
+        let mut edges: Vec<(usize, usize)> = directed
+            .keys()
+            .map(|&(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        let mut problems = Vec::new();
+        for (a, b) in edges {
+            let forward = directed.get(&(a, b)).copied().unwrap_or(0);
+            let backward = directed.get(&(b, a)).copied().unwrap_or(0);
+            let kind = match (forward, backward) {
+                (1, 1) => continue,
+                (f, b) if f + b == 1 => ProblemKind::OpenEdge,
+                (f, b) if f + b > 2 => ProblemKind::NonManifoldEdge,
+                _ => ProblemKind::FlippedEdge,
+            };
+            problems.push(Problem {
+                kind,
+                position: midpoint(self.vertices[a], self.vertices[b]),
+            });
+        }
+        problems
+    }
+
+    fn degenerate_triangles(&self) -> Vec<Problem> {
+        (self.triangles.iter())
+            .filter(|triangle| {
+                let [a, b, c] = self.points(triangle);
+                let longest = [length(sub(b, a)), length(sub(c, b)), length(sub(a, c))]
+                    .into_iter()
+                    .fold(0.0, f64::max);
+                let height = length(cross(sub(b, a), sub(c, a))) / longest;
+                longest == 0.0 || height < WELD_TOLERANCE || longest > MAX_ASPECT_RATIO * height
+            })
+            .map(|triangle| Problem {
+                kind: ProblemKind::Degenerate,
+                position: centroid(self.points(triangle)),
+            })
+            .collect()
+    }
+
+    // Triangles are sorted into uniform grid of cells they pass through, each triangle is tested
+    // against triangles sharing any of its cells, for edge of one crossing interior of another.
+    // Triangles sharing a vertex and coplanar overlaps are not tested.
+    fn self_intersections(&self) -> Vec<Problem> {
+        if self.triangles.is_empty() {
+            return Vec::new();
+        }
+        let bounds: Vec<(Point, Point)> = (self.triangles.iter())
+            .map(|triangle| bounding_box(self.points(triangle)))
+            .collect();
+        let mean_size = bounds
+            .iter()
+            .map(|(min, max)| (0..3).map(|k| max[k] - min[k]).fold(0.0, f64::max))
+            .sum::<f64>()
+            / { bounds.len() as f64 };
+        let cell_size = mean_size.max(WELD_TOLERANCE);
+        // Long thin triangles (like fans of roller ends) are split, so that they are not
+        // put into every cell of their bounding boxes
+        let mut cells = Vec::new();
+        let mut offsets = vec![0];
+        for triangle in &self.triangles {
+            let start = cells.len();
+            covered_cells(self.points(triangle), cell_size, &mut cells);
+            cells[start..].sort_unstable();
+            let count = start + partition_dedup(&mut cells[start..]);
+            cells.truncate(count);
+            offsets.push(cells.len());
+        }
+        let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        for (n, range) in offsets.windows(2).enumerate() {
+            for &key in &cells[range[0]..range[1]] {
+                grid.entry(key).or_default().push(n);
+            }
+        }
+        // Last triangle tested against each one, so that pairs sharing several cells are tested once
+        let mut tested_with = vec![usize::MAX; self.triangles.len()];
+        let mut problems = Vec::new();
+        for (first, range) in offsets.windows(2).enumerate() {
+            let (min_1, max_1) = bounds[first];
+            let triangle_1 = self.triangles[first];
+            for key in &cells[range[0]..range[1]] {
+                for &second in &grid[key] {
+                    if second <= first || tested_with[second] == first {
+                        continue;
+                    }
+                    tested_with[second] = first;
+                    let (min_2, max_2) = bounds[second];
+                    if (0..3).any(|k| min_1[k] > max_2[k] || min_2[k] > max_1[k]) {
+                        continue;
+                    }
+                    let triangle_2 = self.triangles[second];
+                    if triangle_1.iter().any(|n| triangle_2.contains(n)) {
+                        continue;
+                    }
+                    let (points_1, points_2) = (self.points(&triangle_1), self.points(&triangle_2));
+                    if let Some(position) = triangles_intersection(points_1, points_2)
+                        .or_else(|| triangles_intersection(points_2, points_1))
+                    {
+                        problems.push(Problem {
+                            kind: ProblemKind::SelfIntersection,
+                            position,
+                        });
+                    }
+                }
+            }
+        }
+        problems
+    }
+}
+
+// Number of crossing triangle pairs, so that generated surface can be checked before writing it
+pub fn count_self_intersections(triangles: &[[Point; 3]]) -> usize {
+    Mesh::from_triangles(triangles).self_intersections().len()
+}
+
+// Problems are listed by kind with coordinates, returns total count
+pub fn report_problems(problems: &[Problem], filename: &str) -> usize {
+    const LISTED_PROBLEMS: usize = 10;
+    for kind in [
+        ProblemKind::OpenEdge,
+        ProblemKind::NonManifoldEdge,
+        ProblemKind::FlippedEdge,
+        ProblemKind::Degenerate,
+        ProblemKind::SelfIntersection,
+    ] {
+        let found: Vec<&Problem> = problems.iter().filter(|p| p.kind == kind).collect();
+        if found.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "warning: Found {} {} in '{}':",
+            found.len(),
+            kind.description(),
+            filename
+        );
+        for problem in found.iter().take(LISTED_PROBLEMS) {
+            let [x, y, z] = problem.position;
+            eprintln!("  at ({:.4}, {:.4}, {:.4})", x, y, z);
+        }
+        if found.len() > LISTED_PROBLEMS {
+            eprintln!("  ... and {} more", found.len() - LISTED_PROBLEMS);
+        }
+    }
+    problems.len()
+}
+
+pub fn validate_stl_file(filename: &str) -> Result<()> {
+    let mesh = Mesh::from_stl_file(filename)?;
+    ensure!(
+        !mesh.triangles.is_empty(),
+        "Mesh '{}' has no triangles",
+        filename
+    );
+    let problems = mesh.find_problems();
+    let count = report_problems(&problems, filename);
+    ensure!(
+        count == 0,
+        "Mesh validation of '{}' failed with {} problems",
+        filename,
+        count
+    );
+    eprintln!(
+        "Mesh '{}' is watertight and consistently oriented ({} triangles, {} vertices)",
+        filename,
+        mesh.triangles.len(),
+        mesh.vertices.len()
+    );
+    Ok(())
+}
+
+// Points are hashed into cells of weld tolerance size, neighbouring cells are searched too
+#[derive(Default)]
+struct Welder {
+    vertices: Vec<Point>,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl Welder {
+    fn index(&mut self, point: Point) -> usize {
+        let key = point.map(|value| (value / WELD_TOLERANCE).floor() as i64);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour = [key[0] + dx, key[1] + dy, key[2] + dz];
+                    if let Some(indices) = self.cells.get(&neighbour) {
+                        for &n in indices {
+                            if length(sub(self.vertices[n], point)) <= WELD_TOLERANCE {
+                                return n;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.vertices.push(point);
+        let n = self.vertices.len() - 1;
+        self.cells.entry(key).or_default().push(n);
+        n
+    }
+}
+
+// Binary file is recognized by its size matching triangles count from header
+fn binary_triangles(data: &[u8]) -> Option<Vec<[Point; 3]>> {
+    const HEADER_SIZE: usize = 84;
+    const FACE_SIZE: usize = 50;
+    if data.len() < HEADER_SIZE {
+        return None;
+    }
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    if data.len() != HEADER_SIZE + count * FACE_SIZE {
+        return None;
+    }
+    let value = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let triangles = data[HEADER_SIZE..]
+        .chunks_exact(FACE_SIZE)
+        .map(|face| {
+            // Normal (first 12 bytes) is ignored, orientation is given by vertex order
+            [0, 1, 2].map(|v| {
+                let offset = 12 + 12 * v;
+                [0, 1, 2].map(|k| value(&face[offset + 4 * k..]))
+            })
+        })
+        .collect();
+    Some(triangles)
+}
+
+fn ascii_triangles(data: &[u8]) -> Result<Vec<[Point; 3]>> {
+    let text = (std::str::from_utf8(data).ok()).context("File is neither binary nor ASCII STL")?;
+    ensure!(
+        text.trim_start().starts_with("solid"),
+        "File is neither binary nor ASCII STL"
+    );
+    let mut points = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        let coordinates = words
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<f64>, _>>()
+            .with_context(|| format!("Invalid vertex line '{}'", line.trim()))?;
+        ensure!(
+            coordinates.len() == 3,
+            "Invalid vertex line '{}'",
+            line.trim()
+        );
+        points.push([coordinates[0], coordinates[1], coordinates[2]]);
+    }
+    ensure!(
+        points.len() % 3 == 0,
+        "Vertex count {} is not multiple of 3",
+        points.len()
+    );
+    Ok(points
+        .chunks_exact(3)
+        .map(|face| [face[0], face[1], face[2]])
+        .collect())
+}
+
+// Crossing point of edge of first triangle with interior of second one
+fn triangles_intersection(edges_of: [Point; 3], triangle: [Point; 3]) -> Option<Point> {
+    const MARGIN: f64 = 1e-9;
+    let [a, b, c] = triangle;
+    let (ab, ac) = (sub(b, a), sub(c, a));
+    for (start, end) in [
+        (edges_of[0], edges_of[1]),
+        (edges_of[1], edges_of[2]),
+        (edges_of[2], edges_of[0]),
+    ] {
+        // Moller-Trumbore ray triangle intersection limited to segment
+        let direction = sub(end, start);
+        let p = cross(direction, ac);
+        let determinant = dot(ab, p);
+        if determinant.abs() < f64::EPSILON * length(direction) * length(ab) * length(ac) {
+            continue;
+        }
+        let s = sub(start, a);
+        let u = dot(s, p) / determinant;
+        let q = cross(s, ab);
+        let v = dot(direction, q) / determinant;
+        let t = dot(ac, q) / determinant;
+        if u > MARGIN && v > MARGIN && u + v < 1.0 - MARGIN && t > MARGIN && t < 1.0 - MARGIN {
+            return Some([0, 1, 2].map(|k| start[k] + t * direction[k]));
+        }
+    }
+    None
+}
+
+// Cells overlapped by bounding boxes of triangle parts, triangle is halved across its longest
+// edge until parts fit into single cell
+fn covered_cells(points: [Point; 3], cell_size: f64, cells: &mut Vec<[i64; 3]>) {
+    let (min, max) = bounding_box(points);
+    if (0..3).all(|k| max[k] - min[k] <= cell_size) {
+        let cell = |point: Point| point.map(|value| (value / cell_size).floor() as i64);
+        let (low, high) = (cell(min), cell(max));
+        for x in low[0]..=high[0] {
+            for y in low[1]..=high[1] {
+                for z in low[2]..=high[2] {
+                    cells.push([x, y, z]);
+                }
+            }
+        }
+        return;
+    }
+    let [a, b, c] = points;
+    let edge = |&(a, b, _): &(Point, Point, Point)| length(sub(b, a));
+    let (a, b, c) =
+        [(a, b, c), (b, c, a), (c, a, b)]
+            .into_iter()
+            .fold((a, b, c), |longest, edges| {
+                if edge(&edges) > edge(&longest) {
+                    edges
+                } else {
+                    longest
+                }
+            });
+    let middle = midpoint(a, b);
+    covered_cells([a, middle, c], cell_size, cells);
+    covered_cells([middle, b, c], cell_size, cells);
+}
+
+// Moves unique values of sorted slice to its start, returns their count
+fn partition_dedup(values: &mut [[i64; 3]]) -> usize {
+    let mut count = 0;
+    for n in 0..values.len() {
+        if count == 0 || values[n] != values[count - 1] {
+            values[count] = values[n];
+            count += 1;
+        }
+    }
+    count
+}
+
+fn bounding_box(points: [Point; 3]) -> (Point, Point) {
+    let min = [0, 1, 2].map(|k| points.iter().map(|p| p[k]).fold(f64::MAX, f64::min));
+    let max = [0, 1, 2].map(|k| points.iter().map(|p| p[k]).fold(f64::MIN, f64::max));
+    (min, max)
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Point, b: Point) -> Point {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: Point, b: Point) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn length(a: Point) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    [0, 1, 2].map(|k| 0.5 * (a[k] + b[k]))
+}
+
+fn centroid([a, b, c]: [Point; 3]) -> Point {
+    [0, 1, 2].map(|k| (a[k] + b[k] + c[k]) / 3.0)
+}
+
+#[cfg(test)]
+fn tetrahedron() -> Vec<[Point; 3]> {
+    let (o, x, y, z) = ([0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]);
+    vec![[o, y, x], [o, x, z], [o, z, y], [x, y, z]]
+}
+
+#[test]
+fn test_closed_mesh() {
+    let mesh = Mesh::from_triangles(&tetrahedron());
+    assert_eq!(mesh.vertices.len(), 4);
+    assert!(mesh.find_problems().is_empty());
+    // Vertices within weld tolerance are merged
+    let mut shifted = tetrahedron();
+    shifted[3][0][0] += 0.5 * WELD_TOLERANCE;
+    assert!(Mesh::from_triangles(&shifted).find_problems().is_empty());
+}
+
+#[test]
+fn test_topology_problems() {
+    let kinds = |triangles: &[[Point; 3]]| -> Vec<ProblemKind> {
+        (Mesh::from_triangles(triangles).find_problems().iter())
+            .map(|problem| problem.kind)
+            .collect()
+    };
+    let mut open = tetrahedron();
+    open.pop();
+    assert_eq!(kinds(&open), vec![ProblemKind::OpenEdge; 3]);
+    let mut flipped = tetrahedron();
+    flipped[3].swap(0, 1);
+    assert_eq!(kinds(&flipped), vec![ProblemKind::FlippedEdge; 3]);
+    let mut sliver = tetrahedron();
+    sliver.push([[2.0, 0.0, 0.0], [3.0, 0.0, 0.0], [4.0, 1e-6, 0.0]]);
+    assert!(kinds(&sliver).contains(&ProblemKind::Degenerate));
+    let mut needle = tetrahedron();
+    needle.push([[2.0, 0.0, 0.0], [102.0, 0.0, 0.0], [52.0, 1e-3, 0.0]]);
+    assert!(kinds(&needle).contains(&ProblemKind::Degenerate));
+}
+
+#[test]
+fn test_empty_stl_file() {
+    let filename = std::env::temp_dir()
+        .join(format!("img2roller_empty_{}.stl", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    std::fs::write(&filename, [0u8; 84]).unwrap();
+    let result = validate_stl_file(&filename);
+    std::fs::remove_file(&filename).unwrap();
+    assert!(result.unwrap_err().to_string().contains("no triangles"));
+}
+
+#[test]
+fn test_self_intersection() {
+    let mut triangles = tetrahedron();
+    // Triangle piercing face x+y+z=1 of tetrahedron
+    triangles.push([[0.5, 0.5, 0.5], [0.1, 0.1, 0.1], [0.2, 0.1, 0.1]]);
+    let problems = Mesh::from_triangles(&triangles).find_problems();
+    let intersection = problems
+        .iter()
+        .find(|problem| problem.kind == ProblemKind::SelfIntersection)
+        .unwrap();
+    let [x, y, z] = intersection.position;
+    assert!((x + y + z - 1.0).abs() < 1e-9);
+}
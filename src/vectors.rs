@@ -55,6 +55,10 @@ impl Vector3 {
         self
     }
 
+    pub fn length(&self) -> f64 {
+        (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
+    }
+
     pub fn xy_perp_clockwise(self) -> Vector3 {
         Vector3 {
             x: -self.y,
@@ -100,6 +104,10 @@ pub fn xy_scalar_product(vec_a: &Vector3, vec_b: &Vector3) -> f64 {
     vec_a.x * vec_b.x + vec_a.y * vec_b.y
 }
 
+pub fn scalar_product(vec_a: &Vector3, vec_b: &Vector3) -> f64 {
+    vec_a.x * vec_b.x + vec_a.y * vec_b.y + vec_a.z * vec_b.z
+}
+
 #[cfg(test)]
 #[test]
 fn test_vector_normalize() {